@@ -0,0 +1,156 @@
+//
+// Annex B: Length delimited bitstream format
+//
+use crate::obu;
+use std::cmp;
+use std::io;
+
+///
+/// probe temporal_unit() nesting
+///
+/// Validate that frame_unit_size and obu_length of the first temporal unit in `buf` are nested
+/// within temporal_unit_size, and the temporal unit starts with OBU_TEMPORAL_DELIMITER.
+/// The temporal unit is checked up to the end of `buf`.
+///
+pub fn probe(buf: &[u8]) -> bool {
+    probe_temporal_unit(buf).is_some()
+}
+
+fn probe_temporal_unit(mut buf: &[u8]) -> Option<()> {
+    let (_, temporal_unit_size) = obu::leb128(&mut buf).ok()?; // leb128()
+    if temporal_unit_size == 0 {
+        return None;
+    }
+
+    // temporal_unit(sz)
+    let mut sz = temporal_unit_size;
+    let mut first_obu = true;
+    while sz > 0 && !buf.is_empty() {
+        let (frame_unit_size_len, frame_unit_size) = obu::leb128(&mut buf).ok()?; // leb128()
+        if frame_unit_size == 0 || sz < frame_unit_size_len + frame_unit_size {
+            return None;
+        }
+        sz -= frame_unit_size_len + frame_unit_size;
+
+        // frame_unit(sz)
+        let mut sz = frame_unit_size;
+        while sz > 0 && !buf.is_empty() {
+            let (obu_length_len, obu_length) = obu::leb128(&mut buf).ok()?; // leb128()
+            if obu_length == 0 || sz < obu_length_len + obu_length {
+                return None;
+            }
+            sz -= obu_length_len + obu_length;
+
+            // open_bitstream_unit(obu_length)
+            let obu = obu::parse_obu_header(&mut &buf[..], obu_length).ok()?;
+            if first_obu && obu.obu_type != obu::OBU_TEMPORAL_DELIMITER {
+                return None;
+            }
+            first_obu = false;
+            buf = &buf[cmp::min(obu_length as usize, buf.len())..];
+        }
+    }
+    Some(())
+}
+
+///
+/// convert temporal_unit() payload into low overhead bitstream format
+///
+/// Each open_bitstream_unit(obu_length) is rewritten with obu_has_size_field=1 and obu_size,
+/// so that the result can be parsed by `obu::ObuIter`.
+///
+pub fn convert_temporal_unit(mut buf: &[u8]) -> io::Result<Vec<u8>> {
+    let invalid = |msg| io::Error::new(io::ErrorKind::InvalidData, msg);
+    let mut data = Vec::with_capacity(buf.len());
+    while !buf.is_empty() {
+        // frame_unit(frame_unit_size)
+        let (_, frame_unit_size) =
+            obu::leb128(&mut buf).map_err(|_| invalid("invalid frame_unit_size"))?; // leb128()
+        if buf.len() < frame_unit_size as usize {
+            return Err(invalid("invalid frame_unit_size"));
+        }
+        let (mut fu, rest) = buf.split_at(frame_unit_size as usize);
+        buf = rest;
+        while !fu.is_empty() {
+            // open_bitstream_unit(obu_length)
+            let (_, obu_length) =
+                obu::leb128(&mut fu).map_err(|_| invalid("invalid obu_length"))?; // leb128()
+            if fu.len() < obu_length as usize {
+                return Err(invalid("invalid obu_length"));
+            }
+            let (unit, rest) = fu.split_at(obu_length as usize);
+            fu = rest;
+            let obu = obu::parse_obu_header(&mut &unit[..], obu_length)?;
+            let payload_pos = obu.header_len as usize;
+            let payload = &unit[payload_pos..payload_pos + obu.obu_size as usize];
+            // obu_header() with obu_has_size_field=1
+            data.push(unit[0] | 0b0000_0010);
+            if obu.obu_extension_flag {
+                data.push(unit[1]);
+            }
+            write_leb128(&mut data, obu.obu_size);
+            data.extend_from_slice(payload);
+        }
+    }
+    Ok(data)
+}
+
+// leb128() encoding
+fn write_leb128(data: &mut Vec<u8>, mut value: u32) {
+    loop {
+        let leb128_byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            data.push(leb128_byte);
+            break;
+        }
+        data.push(leb128_byte | 0x80);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // frame_unit(): OBU_TEMPORAL_DELIMITER, OBU_PADDING with 2 bytes payload
+    const FRAME_UNIT: [u8; 8] = [0x02, 0x01, 0x10, 0x04, 0x03, 0x78, 0xaa, 0xbb];
+
+    #[test]
+    fn probe_temporal_unit() {
+        let mut tu = vec![FRAME_UNIT.len() as u8];
+        tu.extend_from_slice(&FRAME_UNIT);
+        assert!(probe(&tu));
+        // temporal unit is checked up to the end of buffer
+        assert!(probe(&tu[..5]));
+        // first OBU is not OBU_TEMPORAL_DELIMITER
+        assert!(!probe(&[0x04, 0x03, 0x02, 0x78, 0xaa]));
+        // obu_length exceeds frame_unit_size
+        assert!(!probe(&[0x04, 0x03, 0x03, 0x10, 0x00]));
+        // frame_unit_size exceeds temporal_unit_size
+        assert!(!probe(&[0x02, 0x05, 0x01, 0x10]));
+        assert!(!probe(&[0x00]));
+    }
+
+    #[test]
+    fn convert() {
+        let data = convert_temporal_unit(&FRAME_UNIT).unwrap();
+        assert_eq!(data, [0x12, 0x00, 0x7a, 0x02, 0xaa, 0xbb]);
+        let obus: Vec<_> = obu::ObuIter::new(&data).map(|obu| obu.unwrap()).collect();
+        assert_eq!(obus.len(), 2);
+        assert_eq!(obus[1].payload, [0xaa, 0xbb]);
+
+        // obu_extension_flag=1
+        let data = convert_temporal_unit(&[0x04, 0x03, 0x7c, 0x48, 0xdd]).unwrap();
+        assert_eq!(data, [0x7e, 0x48, 0x01, 0xdd]);
+    }
+
+    #[test]
+    fn convert_invalid() {
+        let err = convert_temporal_unit(&FRAME_UNIT[..6]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        let err = convert_temporal_unit(&[0x02, 0x03, 0x10]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        let err = convert_temporal_unit(&[0x80]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
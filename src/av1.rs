@@ -1,9 +1,12 @@
 //
 // https://aomedia.org/av1-bitstream-and-decoding-process-specification/
 //
+use crate::captions;
+use crate::hdr10plus;
 use crate::obu;
+use crate::timecode;
 
-use crate::obu::NUM_REF_FRAMES;
+use crate::obu::{ParseError, ParseErrorKind, MAX_SEGMENTS, NUM_REF_FRAMES, SEG_LVL_MAX};
 
 pub const INTRA_FRAME: usize = 0;
 pub const LAST_FRAME: usize = 1;
@@ -14,21 +17,557 @@ pub const BWDREF_FRAME: usize = 5;
 pub const ALTREF2_FRAME: usize = 6;
 pub const ALTREF_FRAME: usize = 7;
 
+///
+/// frame_header_obu() result
+///
+#[derive(Debug, PartialEq)]
+pub enum FrameHeaderObu {
+    Header,       // uncompressed_header() is parsed into Sequence::fh
+    Copy,         // frame_header_copy() is identical to the previous frame header
+    CopyMismatch, // frame_header_copy() differs from the previous frame header
+}
+
+///
+/// Mismatch between declared scalability mode and observed layers
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ScalabilityError {
+    // spatial_id/temporal_id outside of the declared layers
+    UndeclaredLayer { spatial_id: u8, temporal_id: u8 },
+    // declared layer is never observed
+    MissingLayer { spatial_id: u8, temporal_id: u8 },
+}
+
+impl std::fmt::Display for ScalabilityError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            ScalabilityError::UndeclaredLayer {
+                spatial_id,
+                temporal_id,
+            } => write!(
+                f,
+                "undeclared layer: spatial_id={} temporal_id={}",
+                spatial_id, temporal_id
+            ),
+            ScalabilityError::MissingLayer {
+                spatial_id,
+                temporal_id,
+            } => write!(
+                f,
+                "missing layer: spatial_id={} temporal_id={}",
+                spatial_id, temporal_id
+            ),
+        }
+    }
+}
+
+///
+/// Stream parser event
+///
+#[derive(Debug)]
+pub enum Event {
+    TemporalDelimiter,
+    SequenceHeader(obu::SequenceHeader),
+    // uncompressed_header() of new frame
+    FrameHeader {
+        decode_order: i64,
+        present_order: Option<i64>, // None if the frame is not shown
+        header: obu::FrameHeader,
+    },
+    // uncompressed_header() with show_existing_frame=1
+    ShowExisting {
+        decode_order: i64, // decode order of the shown frame
+        present_order: i64,
+        header: obu::FrameHeader, // loaded by reference frame loading process
+    },
+    FrameHeaderCopy, // frame_header_copy() is identical to the current frame header
+    TileGroup(obu::TileGroup),
+    TileList(obu::TileList),
+    Metadata(obu::MetadataObu),
+    // HDR10+ metadata in ITU-T T.35 metadata for the next shown frame
+    Hdr10Plus {
+        present_order: i64,
+        metadata: hdr10plus::Hdr10PlusMetadata,
+    },
+    // A/53 closed captions in ITU-T T.35 metadata for the next shown frame
+    CcData {
+        present_order: i64,
+        cc_data: captions::CcData,
+    },
+    // metadata_timecode() for the next shown frame
+    Timecode {
+        present_order: i64,
+        timecode: timecode::Timecode,
+    },
+    Padding(usize), // obu_size of padding OBU
+    Dropped,        // OBU is not in the chosen operating point
+    Warning(Warning),
+}
+
+///
+/// Non-fatal stream error
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Warning {
+    // syntax element parse error
+    Invalid {
+        syntax: &'static str,
+        error: ParseError,
+    },
+    // frame header OBU before sequence header OBU
+    NoSequenceHeader,
+    // tile group OBU without frame header
+    NoFrameHeader,
+    // frame_header_copy() differs from the current frame header
+    FrameHeaderCopyMismatch,
+    // metadata_timecode() continuity error
+    Timecode(timecode::TimecodeError),
+}
+
+impl std::fmt::Display for Warning {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            Warning::Invalid { syntax, error } => write!(f, "invalid {}: {}", syntax, error),
+            Warning::NoSequenceHeader => write!(f, "no sequence header"),
+            Warning::NoFrameHeader => write!(f, "no frame header"),
+            Warning::FrameHeaderCopyMismatch => write!(f, "frame_header_copy() mismatch"),
+            Warning::Timecode(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+///
+/// AV1 stream parser
+///
+/// StreamParser runs sequence header tracking, show_existing_frame handling and
+/// reference frame output/update processes on `seq`, and reports them as events.
+/// Dynamic metadata (HDR10+, closed captions, timecode) is interpreted and reported as events,
+/// `seq` holds only the decoding state defined by the specification.
+///
+#[derive(Debug)]
+pub struct StreamParser {
+    pub seq: Sequence,
+    pub timecode: timecode::TimecodeTracker, // metadata_timecode() state
+    pub layers: LayerTracker,                // declared and observed scalability layers
+}
+
+impl StreamParser {
+    pub fn new(operating_point: usize) -> Self {
+        let mut seq = Sequence::new();
+        seq.operating_point = operating_point;
+        StreamParser {
+            seq,
+            timecode: timecode::TimecodeTracker::new(),
+            layers: LayerTracker::new(),
+        }
+    }
+
+    /// process OBU(Open Bitstream Unit), return events in bitstream order
+    pub fn parse_obu(&mut self, obu: &obu::Obu, payload: &[u8]) -> Vec<Event> {
+        let mut events = Vec::new();
+        let obu_index = self.seq.obu_count;
+        self.seq.obu_count += 1;
+        self.layers.observe_layer(obu);
+        if self.seq.drop_obu(obu) {
+            events.push(Event::Dropped);
+            return events;
+        }
+        let invalid = |syntax, error: ParseError| {
+            Event::Warning(Warning::Invalid {
+                syntax,
+                error: error.with_obu_index(obu_index),
+            })
+        };
+        match obu.obu_type {
+            obu::OBU_SEQUENCE_HEADER => match obu::parse_sequence_header(payload) {
+                Ok(sh) => {
+                    let operating_points_cnt = sh.operating_points_cnt as usize;
+                    self.seq.sh = Some(sh.clone());
+                    events.push(Event::SequenceHeader(sh));
+                    if self.seq.operating_point >= operating_points_cnt {
+                        // operatingPoint shall be in the range 0..operating_points_cnt_minus_1
+                        let kind = ParseErrorKind::InvalidValue {
+                            element: "operating_point",
+                            value: self.seq.operating_point as i64,
+                        };
+                        events.push(invalid("SequenceHeader", ParseError::new(kind, 0)));
+                    }
+                }
+                Err(err) => events.push(invalid("SequenceHeader", err)),
+            },
+            obu::OBU_TEMPORAL_DELIMITER => {
+                self.seq.temporal_delimiter();
+                events.push(Event::TemporalDelimiter);
+            }
+            obu::OBU_FRAME_HEADER | obu::OBU_REDUNDANT_FRAME_HEADER | obu::OBU_FRAME => {
+                if self.seq.sh.is_none() {
+                    events.push(Event::Warning(Warning::NoSequenceHeader));
+                    return events;
+                }
+                let header_len = match self.seq.frame_header_obu(obu, payload) {
+                    Ok((FrameHeaderObu::Header, header_len)) => {
+                        events.push(self.decode_frame_wrapup());
+                        header_len
+                    }
+                    Ok((FrameHeaderObu::Copy, header_len)) => {
+                        events.push(Event::FrameHeaderCopy);
+                        header_len
+                    }
+                    Ok((FrameHeaderObu::CopyMismatch, header_len)) => {
+                        events.push(Event::Warning(Warning::FrameHeaderCopyMismatch));
+                        header_len
+                    }
+                    Err(err) => {
+                        events.push(invalid("FrameHeader", err));
+                        return events;
+                    }
+                };
+                if obu.obu_type == obu::OBU_FRAME {
+                    // byte_alignment()
+                    match self.seq.tile_group_obu(&payload[header_len..]) {
+                        Ok(tg) => events.push(Event::TileGroup(tg)),
+                        Err(err) => {
+                            // bit position from the beginning of OBU_FRAME payload
+                            let err = ParseError {
+                                bit_position: err.bit_position + header_len as u64 * 8,
+                                ..err
+                            };
+                            events.push(invalid("TileGroup", err));
+                        }
+                    }
+                }
+            }
+            obu::OBU_TILE_GROUP => {
+                if !self.seq.seen_frame_header {
+                    events.push(Event::Warning(Warning::NoFrameHeader));
+                    return events;
+                }
+                match self.seq.tile_group_obu(payload) {
+                    Ok(tg) => events.push(Event::TileGroup(tg)),
+                    Err(err) => events.push(invalid("TileGroup", err)),
+                }
+            }
+            obu::OBU_TILE_LIST => match self.seq.tile_list_obu(payload) {
+                Ok(tl) => events.push(Event::TileList(tl)),
+                Err(err) => events.push(invalid("TileList", err)),
+            },
+            obu::OBU_METADATA => match obu::parse_metadata_obu(payload) {
+                Ok(metadata) => {
+                    let metadata_events = self.metadata_obu(&metadata);
+                    events.push(Event::Metadata(metadata));
+                    events.extend(metadata_events);
+                }
+                Err(err) => events.push(invalid("MetadataObu", err)),
+            },
+            obu::OBU_PADDING => events.push(Event::Padding(payload.len())),
+            _ => {}
+        }
+        events
+    }
+
+    // metadata_obu(): interpret dynamic metadata for the next shown frame
+    fn metadata_obu(&mut self, metadata: &obu::MetadataObu) -> Vec<Event> {
+        let mut events = Vec::new();
+        let present_order = self.seq.rfman.present_order;
+        match *metadata {
+            obu::MetadataObu::Scalability(ref sc) => {
+                self.layers.scalability = Some(sc.clone());
+            }
+            obu::MetadataObu::Timecode(ref tc) => {
+                // nominal frame rate for n_frames counting (frame_rate() has nonzero denominator)
+                let (num, den) = self.seq.frame_rate();
+                let fps = num.div_ceil(den);
+                let (timecode, errors) = self.timecode.update(present_order, tc, fps);
+                events.push(Event::Timecode {
+                    present_order,
+                    timecode,
+                });
+                for err in errors {
+                    events.push(Event::Warning(Warning::Timecode(err)));
+                }
+            }
+            obu::MetadataObu::ItutT35(ref t35) => {
+                if let Some(metadata) = hdr10plus::parse_hdr10plus_metadata(t35) {
+                    events.push(Event::Hdr10Plus {
+                        present_order,
+                        metadata,
+                    });
+                }
+                if let Some(cc_data) = captions::parse_a53_cc_data(t35) {
+                    events.push(Event::CcData {
+                        present_order,
+                        cc_data,
+                    });
+                }
+            }
+            _ => {}
+        }
+        events
+    }
+
+    // decode_frame_wrapup(): Decode frame wrapup process
+    fn decode_frame_wrapup(&mut self) -> Event {
+        let seq = &mut self.seq;
+        let fh = seq.fh.as_ref().unwrap(); // parsed by frame_header_obu()
+        let rfman = &mut seq.rfman;
+        let event = if fh.show_existing_frame {
+            Event::ShowExisting {
+                decode_order: rfman.frame_buf[fh.frame_to_show_map_idx as usize],
+                present_order: rfman.present_order,
+                header: fh.clone(),
+            }
+        } else {
+            Event::FrameHeader {
+                decode_order: rfman.decode_order,
+                present_order: if fh.show_frame {
+                    Some(rfman.present_order)
+                } else {
+                    None
+                },
+                header: fh.clone(),
+            }
+        };
+        if fh.show_frame || fh.show_existing_frame {
+            rfman.output_process(fh);
+        }
+        if !fh.show_existing_frame || fh.frame_type == obu::KEY_FRAME {
+            rfman.update_process(fh);
+        }
+        event
+    }
+}
+
+///
+/// Scalability layer tracker
+///
+#[derive(Debug, Default)]
+pub struct LayerTracker {
+    pub scalability: Option<obu::ScalabilityMetadata>, // declared metadata_scalability()
+    pub observed_layers: [u8; 4], // bitmask of observed temporal_id for each spatial_id
+}
+
+impl LayerTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// record temporal_id/spatial_id of frame OBUs
+    pub fn observe_layer(&mut self, obu: &obu::Obu) {
+        match obu.obu_type {
+            obu::OBU_FRAME_HEADER | obu::OBU_FRAME | obu::OBU_TILE_GROUP => {
+                // temporal_id and spatial_id are 0 without obu_extension_header()
+                self.observed_layers[obu.spatial_id as usize] |= 1 << obu.temporal_id;
+            }
+            _ => {}
+        }
+    }
+
+    /// compare observed layers with the declared scalability mode
+    pub fn check_scalability(&self) -> Vec<ScalabilityError> {
+        let mut errors = Vec::new();
+        let (spatial_layers, temporal_layers) =
+            match self.scalability.as_ref().and_then(|sc| sc.layers()) {
+                Some(layers) => layers,
+                None => return errors,
+            };
+        for spatial_id in 0..4 {
+            for temporal_id in 0..8 {
+                let declared = spatial_id < spatial_layers && temporal_id < temporal_layers;
+                let observed = (self.observed_layers[spatial_id as usize] >> temporal_id) & 1 != 0;
+                if observed && !declared {
+                    errors.push(ScalabilityError::UndeclaredLayer {
+                        spatial_id,
+                        temporal_id,
+                    });
+                } else if declared && !observed && temporal_layers < 8 {
+                    errors.push(ScalabilityError::MissingLayer {
+                        spatial_id,
+                        temporal_id,
+                    });
+                }
+            }
+        }
+        errors
+    }
+}
+
 ///
 /// Sequence
 ///
 #[derive(Debug)]
 pub struct Sequence {
     pub sh: Option<obu::SequenceHeader>,
+    pub fh: Option<obu::FrameHeader>, // frame header of current frame
     pub rfman: RefFrameManager,
+    pub operating_point: usize,  // choose_operating_point()
+    pub seen_frame_header: bool, // SeenFrameHeader
+    frame_header_bits: Vec<u8>,  // frame header bits of current frame (zero padded)
+    frame_header_size: u64,      // frame header size of current frame in bits
+    tile_num: u16,               // TileNum of the next tile group in current frame
+    pub anchor_frames: Vec<i64>, // decode order of anchor frames for large scale tile
+    decoded_frames: Vec<i64>, // decode order of frames with tile data since the last tile list OBU
+    pub obu_count: usize,     // number of processed OBUs
 }
 
 impl Sequence {
     pub fn new() -> Self {
         Sequence {
             sh: None,
+            fh: None,
             rfman: RefFrameManager::new(),
+            operating_point: 0,
+            seen_frame_header: false,
+            frame_header_bits: Vec::new(),
+            frame_header_size: 0,
+            tile_num: 0,
+            anchor_frames: Vec::new(),
+            decoded_frames: Vec::new(),
+            obu_count: 0,
+        }
+    }
+
+    /// temporal_delimiter_obu()
+    pub fn temporal_delimiter(&mut self) {
+        self.seen_frame_header = false;
+    }
+
+    ///
+    /// frame_header_obu(), return (result, frame header size in bytes)
+    ///
+    /// `payload` is the whole OBU payload of OBU_FRAME_HEADER, OBU_REDUNDANT_FRAME_HEADER or OBU_FRAME.
+    /// The caller should invoke decode_frame_wrapup() (see `StreamParser`) when the result is `FrameHeaderObu::Header`.
+    ///
+    pub fn frame_header_obu(
+        &mut self,
+        obu: &obu::Obu,
+        payload: &[u8],
+    ) -> Result<(FrameHeaderObu, usize), ParseError> {
+        if self.seen_frame_header {
+            // frame_header_copy() has the same size as the frame header of current frame
+            let header_bits =
+                obu::leading_bits(payload, self.frame_header_size).ok_or_else(|| {
+                    ParseError::new(ParseErrorKind::Truncated, payload.len() as u64 * 8)
+                })?;
+            let result = if header_bits == self.frame_header_bits {
+                FrameHeaderObu::Copy
+            } else {
+                FrameHeaderObu::CopyMismatch
+            };
+            // byte_alignment() after frame_header_copy()
+            return Ok((result, self.frame_header_size.div_ceil(8) as usize));
+        }
+
+        let sh = self
+            .sh
+            .as_ref()
+            .ok_or_else(|| ParseError::new(ParseErrorKind::Conformance("no sequence header"), 0))?;
+        let (fh, header_size) = obu::parse_frame_header(payload, obu, sh, &mut self.rfman)?;
+        self.frame_header_bits = obu::leading_bits(payload, header_size).unwrap_or_default();
+        self.frame_header_size = header_size;
+        self.tile_num = 0; // TileNum
+        self.seen_frame_header = !fh.show_existing_frame;
+        self.fh = Some(fh);
+        // byte_alignment() after frame_header_obu()
+        let header_len = header_size.div_ceil(8) as usize;
+        Ok((FrameHeaderObu::Header, header_len))
+    }
+
+    /// tile_group_obu()
+    pub fn tile_group_obu(&mut self, data: &[u8]) -> Result<obu::TileGroup, ParseError> {
+        let fh = match self.fh {
+            Some(ref fh) if self.seen_frame_header => fh,
+            _ => {
+                return Err(ParseError::new(
+                    ParseErrorKind::Conformance("no frame header"),
+                    0,
+                ))
+            }
+        };
+        let tg = obu::parse_tile_group(data, fh, self.tile_num)?;
+        self.tile_num = tg.tg_end + 1;
+        let num_tiles = fh.tile_info.tile_cols * fh.tile_info.tile_rows;
+        if tg.tg_end == num_tiles - 1 {
+            // decode_frame_wrapup()
+            self.seen_frame_header = false;
+            // decode_frame_wrapup() has been invoked with the frame header
+            self.decoded_frames.push(self.rfman.decode_order - 1);
+        }
+        Ok(tg)
+    }
+
+    /// tile_list_obu()
+    pub fn tile_list_obu(&mut self, data: &[u8]) -> Result<obu::TileList, ParseError> {
+        let tl = obu::parse_tile_list(data)?;
+        if !self.decoded_frames.is_empty() {
+            // first tile list OBU of large scale tile group
+            self.anchor_frames = std::mem::take(&mut self.decoded_frames);
+        }
+        Ok(tl)
+    }
+
+    ///
+    /// decode order of the anchor frame referred by tile_list_entry()
+    ///
+    /// Anchor frames are not signaled in the bitstream. They are assumed to be the frames with
+    /// tile data decoded between the previous tile list group and the first tile list OBU of
+    /// the current group, in decoding order.
+    ///
+    pub fn anchor_frame(&self, tle: &obu::TileListEntry) -> Option<i64> {
+        self.anchor_frames
+            .get(tle.anchor_frame_idx as usize)
+            .cloned()
+    }
+
+    ///
+    /// frame rate (numerator, denominator) from timing_info()
+    ///
+    /// 30000/1001 if timing_info() is not present, the picture interval is not signaled
+    /// (equal_picture_interval=0) or the values are invalid.
+    ///
+    pub fn frame_rate(&self) -> (u32, u32) {
+        match self.sh {
+            Some(ref sh)
+                if sh.timing_info_present_flag && sh.timing_info.equal_picture_interval =>
+            {
+                let ti = &sh.timing_info;
+                let den = ti
+                    .num_units_in_display_tick
+                    .checked_mul(ti.num_ticks_per_picture);
+                match den {
+                    Some(den) if den > 0 && ti.time_scale > 0 => (ti.time_scale, den),
+                    _ => (30000, 1001),
+                }
+            }
+            _ => (30000, 1001),
+        }
+    }
+
+    /// OperatingPointIdc of the chosen operating point (fallback to operating point 0 if out of range)
+    pub fn operating_point_idc(&self) -> u16 {
+        match self.sh {
+            Some(ref sh) => {
+                sh.op
+                    .get(self.operating_point)
+                    .unwrap_or(&sh.op[0])
+                    .operating_point_idc
+            }
+            None => 0,
+        }
+    }
+
+    /// return true if the OBU should be dropped by drop_obu()
+    pub fn drop_obu(&self, obu: &obu::Obu) -> bool {
+        let op_idc = self.operating_point_idc();
+        if obu.obu_type != obu::OBU_SEQUENCE_HEADER
+            && obu.obu_type != obu::OBU_TEMPORAL_DELIMITER
+            && op_idc != 0
+            && obu.obu_extension_flag
+        {
+            let in_temporal_layer = (op_idc >> obu.temporal_id) & 1 != 0;
+            let in_spatial_layer = (op_idc >> (obu.spatial_id + 8)) & 1 != 0;
+            return !in_temporal_layer || !in_spatial_layer;
         }
+        false
     }
 }
 
@@ -37,11 +576,22 @@ impl Sequence {
 ///
 #[derive(Debug)]
 pub struct RefFrameManager {
-    pub ref_valid: [bool; NUM_REF_FRAMES],    // RefValid[i]
-    pub ref_frame_id: [u16; NUM_REF_FRAMES],  // RefFrameId[i]
-    pub ref_frame_type: [u8; NUM_REF_FRAMES], // RefFrameType[i]
-    pub ref_order_hint: [u8; NUM_REF_FRAMES], // RefOrderHint[i]
+    pub ref_valid: [bool; NUM_REF_FRAMES],         // RefValid[i]
+    pub ref_frame_id: [u16; NUM_REF_FRAMES],       // RefFrameId[i]
+    pub ref_frame_type: [u8; NUM_REF_FRAMES],      // RefFrameType[i]
+    pub ref_order_hint: [u8; NUM_REF_FRAMES],      // RefOrderHint[i]
+    pub ref_upscaled_width: [u32; NUM_REF_FRAMES], // RefUpscaledWidth[i]
+    pub ref_frame_width: [u32; NUM_REF_FRAMES],    // RefFrameWidth[i]
+    pub ref_frame_height: [u32; NUM_REF_FRAMES],   // RefFrameHeight[i]
+    pub ref_render_width: [u32; NUM_REF_FRAMES],   // RefRenderWidth[i]
+    pub ref_render_height: [u32; NUM_REF_FRAMES],  // RefRenderHeight[i]
+    pub saved_order_hints: [[u8; NUM_REF_FRAMES]; NUM_REF_FRAMES], // SavedOrderHints[i][j]
     pub saved_gm_params: [[[i32; 6]; NUM_REF_FRAMES]; NUM_REF_FRAMES], // SavedGmParams[i][ref][j]
+    pub saved_film_grain_params: [obu::FilmGrainParams; NUM_REF_FRAMES], // film_grain_params() of slot i
+    pub saved_loop_filter_ref_deltas: [[i32; NUM_REF_FRAMES]; NUM_REF_FRAMES], // SavedLoopFilterRefDeltas[i][j]
+    pub saved_loop_filter_mode_deltas: [[i32; 2]; NUM_REF_FRAMES], // SavedLoopFilterModeDeltas[i][j]
+    pub saved_feature_enabled: [[[bool; SEG_LVL_MAX]; MAX_SEGMENTS]; NUM_REF_FRAMES], // SavedFeatureEnabled[i][j][k]
+    pub saved_feature_data: [[[i32; SEG_LVL_MAX]; MAX_SEGMENTS]; NUM_REF_FRAMES], // SavedFeatureData[i][j][k]
     // user data
     pub decode_order: i64,  // frame decoding oreder
     pub present_order: i64, // frame presentation order
@@ -55,7 +605,18 @@ impl RefFrameManager {
             ref_frame_id: [0; NUM_REF_FRAMES],
             ref_frame_type: [0; NUM_REF_FRAMES],
             ref_order_hint: [0; NUM_REF_FRAMES],
+            ref_upscaled_width: [0; NUM_REF_FRAMES],
+            ref_frame_width: [0; NUM_REF_FRAMES],
+            ref_frame_height: [0; NUM_REF_FRAMES],
+            ref_render_width: [0; NUM_REF_FRAMES],
+            ref_render_height: [0; NUM_REF_FRAMES],
+            saved_order_hints: [[0; NUM_REF_FRAMES]; NUM_REF_FRAMES],
             saved_gm_params: [[[0; 6]; NUM_REF_FRAMES]; NUM_REF_FRAMES],
+            saved_film_grain_params: Default::default(),
+            saved_loop_filter_ref_deltas: [[0; NUM_REF_FRAMES]; NUM_REF_FRAMES],
+            saved_loop_filter_mode_deltas: [[0; 2]; NUM_REF_FRAMES],
+            saved_feature_enabled: [[[false; SEG_LVL_MAX]; MAX_SEGMENTS]; NUM_REF_FRAMES],
+            saved_feature_data: [[[0; SEG_LVL_MAX]; MAX_SEGMENTS]; NUM_REF_FRAMES],
             decode_order: 0,
             present_order: 0,
             frame_buf: [i64::min_value(); NUM_REF_FRAMES],
@@ -88,6 +649,31 @@ impl RefFrameManager {
         }
     }
 
+    /// load_grain_params(idx)
+    pub fn load_grain_params(&self, idx: usize) -> obu::FilmGrainParams {
+        self.saved_film_grain_params[idx].clone()
+    }
+
+    /// Reference frame loading process
+    pub fn load_process(&self, fh: &mut obu::FrameHeader) {
+        let idx = fh.frame_to_show_map_idx as usize;
+        fh.current_frame_id = self.ref_frame_id[idx];
+        fh.frame_size.upscaled_width = self.ref_upscaled_width[idx];
+        fh.frame_size.frame_width = self.ref_frame_width[idx];
+        fh.frame_size.frame_height = self.ref_frame_height[idx];
+        fh.render_size.render_width = self.ref_render_width[idx];
+        fh.render_size.render_height = self.ref_render_height[idx];
+        fh.order_hint = self.ref_order_hint[idx];
+        fh.order_hints = self.saved_order_hints[idx];
+        fh.global_motion_params.gm_params = self.saved_gm_params[idx];
+        // load_loop_filter_params(idx)
+        fh.loop_filter_params.loop_filter_ref_deltas = self.saved_loop_filter_ref_deltas[idx];
+        fh.loop_filter_params.loop_filter_mode_deltas = self.saved_loop_filter_mode_deltas[idx];
+        // load_segmentation_params(idx)
+        fh.segmentation_params.feature_enabled = self.saved_feature_enabled[idx];
+        fh.segmentation_params.feature_data = self.saved_feature_data[idx];
+    }
+
     /// Output process
     pub fn output_process(&mut self, _: &obu::FrameHeader) {
         self.present_order += 1;
@@ -95,23 +681,46 @@ impl RefFrameManager {
 
     /// Reference frame update process
     pub fn update_process(&mut self, fh: &obu::FrameHeader) {
+        // show_existing_frame of KEY_FRAME refreshes slots with the shown frame
+        let frame_buf = if fh.show_existing_frame {
+            self.frame_buf[fh.frame_to_show_map_idx as usize]
+        } else {
+            self.decode_order
+        };
         for i in 0..NUM_REF_FRAMES {
             if (fh.refresh_frame_flags >> i) & 1 == 1 {
                 self.ref_valid[i] = true;
                 self.ref_frame_id[i] = fh.current_frame_id;
                 self.ref_frame_type[i] = fh.frame_type;
                 self.ref_order_hint[i] = fh.order_hint;
+                self.ref_upscaled_width[i] = fh.frame_size.upscaled_width;
+                self.ref_frame_width[i] = fh.frame_size.frame_width;
+                self.ref_frame_height[i] = fh.frame_size.frame_height;
+                self.ref_render_width[i] = fh.render_size.render_width;
+                self.ref_render_height[i] = fh.render_size.render_height;
+                self.saved_order_hints[i] = fh.order_hints;
                 for ref_ in LAST_FRAME..=ALTREF_FRAME {
                     for j in 0..=5 {
                         self.saved_gm_params[i][ref_][j] =
                             fh.global_motion_params.gm_params[ref_][j];
                     }
                 }
+                // save_grain_params(i)
+                self.saved_film_grain_params[i] = fh.film_grain_params.clone();
+                // save_loop_filter_params(i)
+                self.saved_loop_filter_ref_deltas[i] = fh.loop_filter_params.loop_filter_ref_deltas;
+                self.saved_loop_filter_mode_deltas[i] =
+                    fh.loop_filter_params.loop_filter_mode_deltas;
+                // save_segmentation_params(i)
+                self.saved_feature_enabled[i] = fh.segmentation_params.feature_enabled;
+                self.saved_feature_data[i] = fh.segmentation_params.feature_data;
                 // user data
-                self.frame_buf[i] = self.decode_order;
+                self.frame_buf[i] = frame_buf;
             }
         }
-        self.decode_order += 1;
+        if !fh.show_existing_frame {
+            self.decode_order += 1;
+        }
     }
 }
 
@@ -164,3 +773,455 @@ pub mod stringify {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bitio::pack_bits;
+
+    fn obu_header(obu_type: u8, extension: Option<(u8, u8)>) -> obu::Obu {
+        let (temporal_id, spatial_id) = extension.unwrap_or((0, 0));
+        obu::Obu {
+            obu_type,
+            obu_extension_flag: extension.is_some(),
+            obu_has_size_field: true,
+            temporal_id,
+            spatial_id,
+            obu_size: 0,
+            header_len: 0,
+        }
+    }
+
+    #[test]
+    fn relative_dist() {
+        let mut sh = obu::SequenceHeader {
+            enable_order_hint: true,
+            order_hint_bits: 7,
+            ..Default::default()
+        };
+        assert_eq!(get_relative_dist(1, 127, &sh), 2);
+        assert_eq!(get_relative_dist(127, 1, &sh), -2);
+        assert_eq!(get_relative_dist(10, 74, &sh), -64);
+        sh.enable_order_hint = false;
+        assert_eq!(get_relative_dist(1, 127, &sh), 0);
+    }
+
+    #[test]
+    fn update_and_load_process() {
+        let mut rfman = RefFrameManager::new();
+        let mut fh = obu::FrameHeader {
+            frame_type: obu::KEY_FRAME,
+            current_frame_id: 3,
+            order_hint: 9,
+            refresh_frame_flags: 0b0000_0101,
+            ..Default::default()
+        };
+        fh.frame_size.upscaled_width = 352;
+        fh.frame_size.frame_width = 352;
+        fh.frame_size.frame_height = 288;
+        fh.render_size.render_width = 320;
+        fh.render_size.render_height = 240;
+        fh.order_hints[LAST_FRAME] = 7;
+        fh.global_motion_params.gm_params[ALTREF_FRAME] = [1, 2, 3, 4, 5, 6];
+        fh.loop_filter_params.loop_filter_ref_deltas = [1, 0, 0, 0, -1, 0, -1, -1];
+        fh.segmentation_params.feature_enabled[1][0] = true;
+        fh.segmentation_params.feature_data[1][0] = -4;
+        rfman.update_process(&fh);
+
+        assert_eq!(rfman.decode_order, 1);
+        assert_eq!(
+            rfman.ref_valid,
+            [true, false, true, false, false, false, false, false]
+        );
+        assert_eq!(rfman.frame_buf[0], 0);
+        assert_eq!(rfman.frame_buf[1], i64::MIN);
+
+        let mut shown = obu::FrameHeader {
+            show_existing_frame: true,
+            frame_to_show_map_idx: 2,
+            ..Default::default()
+        };
+        rfman.load_process(&mut shown);
+        assert_eq!(shown.current_frame_id, 3);
+        assert_eq!(shown.order_hint, 9);
+        assert_eq!(shown.order_hints[LAST_FRAME], 7);
+        assert_eq!(
+            (shown.frame_size.frame_width, shown.frame_size.frame_height),
+            (352, 288)
+        );
+        assert_eq!(
+            (
+                shown.render_size.render_width,
+                shown.render_size.render_height
+            ),
+            (320, 240)
+        );
+        assert_eq!(
+            shown.global_motion_params.gm_params[ALTREF_FRAME],
+            [1, 2, 3, 4, 5, 6]
+        );
+        assert_eq!(
+            shown.loop_filter_params.loop_filter_ref_deltas,
+            [1, 0, 0, 0, -1, 0, -1, -1]
+        );
+        assert!(shown.segmentation_params.feature_enabled[1][0]);
+        assert_eq!(shown.segmentation_params.feature_data[1][0], -4);
+
+        // show_existing_frame of KEY_FRAME refreshes all slots with the shown frame
+        shown.frame_type = obu::KEY_FRAME;
+        shown.refresh_frame_flags = 0xff;
+        rfman.update_process(&shown);
+        assert_eq!(rfman.decode_order, 1);
+        assert_eq!(rfman.frame_buf, [0; NUM_REF_FRAMES]);
+        assert_eq!(rfman.ref_order_hint, [9; NUM_REF_FRAMES]);
+    }
+
+    #[test]
+    fn mark_ref_frames() {
+        let sh = obu::SequenceHeader {
+            frame_id_numbers_present_flag: true,
+            delta_frame_id_length: 4,
+            ..Default::default()
+        };
+        let mut rfman = RefFrameManager::new();
+        rfman.ref_valid = [true; NUM_REF_FRAMES];
+        rfman.ref_frame_id = [3, 4, 20, 21, 10, 10, 10, 10];
+        let fh = obu::FrameHeader {
+            current_frame_id: 20,
+            ..Default::default()
+        };
+        rfman.mark_ref_frames(8, &sh, &fh);
+        assert_eq!(
+            rfman.ref_valid,
+            [false, true, true, false, true, true, true, true]
+        );
+
+        // current_frame_id wrapped around
+        rfman.ref_valid = [true; NUM_REF_FRAMES];
+        rfman.ref_frame_id = [5, 6, 244, 245, 250, 0, 0, 0];
+        let fh = obu::FrameHeader {
+            current_frame_id: 5,
+            ..Default::default()
+        };
+        rfman.mark_ref_frames(8, &sh, &fh);
+        assert_eq!(
+            rfman.ref_valid,
+            [true, false, false, true, true, true, true, true]
+        );
+    }
+
+    #[test]
+    fn drop_obu_by_operating_point() {
+        let mut seq = Sequence::new();
+        let mut sh = obu::SequenceHeader::default();
+        sh.op.push(obu::OperatingPoint {
+            operating_point_idc: 0x103, // spatial layer 0, temporal layer 0-1
+            ..Default::default()
+        });
+        sh.op.push(Default::default());
+        seq.sh = Some(sh);
+
+        assert!(!seq.drop_obu(&obu_header(obu::OBU_FRAME, Some((1, 0)))));
+        assert!(seq.drop_obu(&obu_header(obu::OBU_FRAME, Some((2, 0)))));
+        assert!(seq.drop_obu(&obu_header(obu::OBU_FRAME, Some((0, 1)))));
+        assert!(!seq.drop_obu(&obu_header(obu::OBU_FRAME, None)));
+        assert!(!seq.drop_obu(&obu_header(obu::OBU_SEQUENCE_HEADER, Some((2, 0)))));
+
+        seq.operating_point = 1;
+        assert_eq!(seq.operating_point_idc(), 0);
+        assert!(!seq.drop_obu(&obu_header(obu::OBU_FRAME, Some((2, 0)))));
+        // out of range operating point falls back to operating point 0
+        seq.operating_point = 2;
+        assert_eq!(seq.operating_point_idc(), 0x103);
+    }
+
+    #[test]
+    fn frame_rate() {
+        let mut seq = Sequence::new();
+        assert_eq!(seq.frame_rate(), (30000, 1001));
+        let mut sh = obu::SequenceHeader {
+            timing_info_present_flag: true,
+            ..Default::default()
+        };
+        sh.timing_info.time_scale = 60000;
+        sh.timing_info.num_units_in_display_tick = 1001;
+        sh.timing_info.equal_picture_interval = true;
+        sh.timing_info.num_ticks_per_picture = 2;
+        seq.sh = Some(sh.clone());
+        assert_eq!(seq.frame_rate(), (60000, 2002));
+
+        // picture interval is not signaled
+        sh.timing_info.equal_picture_interval = false;
+        sh.timing_info.num_ticks_per_picture = 0;
+        seq.sh = Some(sh.clone());
+        assert_eq!(seq.frame_rate(), (30000, 1001));
+        sh.timing_info.equal_picture_interval = true;
+        sh.timing_info.num_ticks_per_picture = 2;
+
+        sh.timing_info.num_units_in_display_tick = 0x8000_0000;
+        seq.sh = Some(sh.clone());
+        assert_eq!(seq.frame_rate(), (30000, 1001));
+        sh.timing_info.num_units_in_display_tick = 0;
+        seq.sh = Some(sh.clone());
+        assert_eq!(seq.frame_rate(), (30000, 1001));
+        sh.timing_info.num_units_in_display_tick = 1;
+        sh.timing_info.time_scale = 0;
+        seq.sh = Some(sh);
+        assert_eq!(seq.frame_rate(), (30000, 1001));
+    }
+
+    // sequence_header_obu() with reduced_still_picture_header, 16x16 4:2:0 8bit
+    fn still_picture_sequence_header() -> Vec<u8> {
+        pack_bits(&[
+            (0, 3),  // seq_profile
+            (1, 1),  // still_picture
+            (1, 1),  // reduced_still_picture_header
+            (0, 5),  // seq_level_idx[0]
+            (3, 4),  // frame_width_bits_minus_1
+            (3, 4),  // frame_height_bits_minus_1
+            (15, 4), // max_frame_width_minus_1
+            (15, 4), // max_frame_height_minus_1
+            (0, 3),  // use_128x128_superblock, enable_filter_intra, enable_intra_edge_filter
+            (0, 3),  // enable_superres, enable_cdef, enable_restoration
+            (0, 4),  // high_bitdepth, mono_chrome, color_description_present_flag, color_range
+            (0, 2),  // chroma_sample_position
+            (0, 1),  // separate_uv_delta_q
+            (0, 1),  // film_grain_params_present
+            (1, 1),  // trailing_one_bit
+        ])
+    }
+
+    #[test]
+    fn sequence_header_operating_point() {
+        let payload = still_picture_sequence_header();
+        let obu = obu_header(obu::OBU_SEQUENCE_HEADER, None);
+
+        let mut parser = StreamParser::new(0);
+        let events = parser.parse_obu(&obu, &payload);
+        assert_eq!(events.len(), 1);
+        match events[0] {
+            Event::SequenceHeader(ref sh) => {
+                assert!(sh.reduced_still_picture_header);
+                assert_eq!((sh.max_frame_width, sh.max_frame_height), (16, 16));
+            }
+            ref ev => panic!("unexpected event {:?}", ev),
+        }
+
+        let mut parser = StreamParser::new(1);
+        let events = parser.parse_obu(&obu, &payload);
+        assert_eq!(events.len(), 2);
+        match events[1] {
+            Event::Warning(warning) => assert_eq!(
+                warning,
+                Warning::Invalid {
+                    syntax: "SequenceHeader",
+                    error: ParseError::new(
+                        ParseErrorKind::InvalidValue {
+                            element: "operating_point",
+                            value: 1,
+                        },
+                        0
+                    )
+                    .with_obu_index(0),
+                }
+            ),
+            ref ev => panic!("unexpected event {:?}", ev),
+        }
+    }
+
+    #[test]
+    fn frame_header_without_sequence_header() {
+        let mut parser = StreamParser::new(0);
+        let events = parser.parse_obu(&obu_header(obu::OBU_FRAME_HEADER, None), &[0x10]);
+        match events[..] {
+            [Event::Warning(Warning::NoSequenceHeader)] => {}
+            ref ev => panic!("unexpected events {:?}", ev),
+        }
+        let events = parser.parse_obu(&obu_header(obu::OBU_TILE_GROUP, None), &[0x00]);
+        match events[..] {
+            [Event::Warning(Warning::NoFrameHeader)] => {}
+            ref ev => panic!("unexpected events {:?}", ev),
+        }
+    }
+
+    #[test]
+    fn scalability_layers() {
+        let mut parser = StreamParser::new(0);
+        // metadata_scalability() with scalability_mode_idc=2(L2T1)
+        parser.parse_obu(&obu_header(obu::OBU_METADATA, None), &[0x03, 0x02, 0x80]);
+        assert_eq!(
+            parser
+                .layers
+                .scalability
+                .as_ref()
+                .and_then(|sc| sc.layers()),
+            Some((2, 1))
+        );
+        assert_eq!(
+            parser.layers.check_scalability(),
+            [
+                ScalabilityError::MissingLayer {
+                    spatial_id: 0,
+                    temporal_id: 0
+                },
+                ScalabilityError::MissingLayer {
+                    spatial_id: 1,
+                    temporal_id: 0
+                },
+            ]
+        );
+
+        // (temporal_id, spatial_id) of frame OBUs
+        for &layer in &[(0, 0), (0, 1)] {
+            parser.parse_obu(&obu_header(obu::OBU_FRAME, Some(layer)), &[]);
+        }
+        assert!(parser.layers.check_scalability().is_empty());
+
+        parser.parse_obu(&obu_header(obu::OBU_FRAME, Some((1, 1))), &[]);
+        parser.parse_obu(&obu_header(obu::OBU_TILE_GROUP, Some((0, 2))), &[]);
+        // non-frame OBUs are not counted
+        parser.parse_obu(
+            &obu_header(obu::OBU_METADATA, Some((2, 0))),
+            &[0x03, 0x02, 0x80],
+        );
+        assert_eq!(
+            parser.layers.check_scalability(),
+            [
+                ScalabilityError::UndeclaredLayer {
+                    spatial_id: 1,
+                    temporal_id: 1
+                },
+                ScalabilityError::UndeclaredLayer {
+                    spatial_id: 2,
+                    temporal_id: 0
+                },
+            ]
+        );
+        assert_eq!(
+            parser.layers.check_scalability()[0].to_string(),
+            "undeclared layer: spatial_id=1 temporal_id=1"
+        );
+    }
+
+    #[test]
+    fn anchor_frames() {
+        let mut parser = StreamParser::new(0);
+        parser.parse_obu(
+            &obu_header(obu::OBU_SEQUENCE_HEADER, None),
+            &still_picture_sequence_header(),
+        );
+        let decode_frame = |parser: &mut StreamParser| {
+            parser.parse_obu(&obu_header(obu::OBU_TEMPORAL_DELIMITER, None), &[]);
+            parser.parse_obu(
+                &obu_header(obu::OBU_FRAME_HEADER, None),
+                &[0x00, 0x00, 0x20],
+            );
+            parser.parse_obu(&obu_header(obu::OBU_TILE_GROUP, None), &[0xaa]);
+        };
+        // tile_list_entry() with anchor_frame_idx=0,1,2
+        let tile_list_obu = [
+            0x00, 0x00, 0x00, 0x02, // 1x1 tiles, 3 tile_list_entry()
+            0x00, 0x00, 0x00, 0x00, 0x00, 0xaa, // anchor #0
+            0x01, 0x00, 0x00, 0x00, 0x00, 0xaa, // anchor #1
+            0x02, 0x00, 0x00, 0x00, 0x00, 0xaa, // anchor #2
+        ];
+        let tile_list = |parser: &mut StreamParser| {
+            let events = parser.parse_obu(&obu_header(obu::OBU_TILE_LIST, None), &tile_list_obu);
+            match events[..] {
+                [Event::TileList(ref tl)] => tl
+                    .tile_list_entries
+                    .iter()
+                    .map(|tle| parser.seq.anchor_frame(tle))
+                    .collect::<Vec<_>>(),
+                ref ev => panic!("unexpected events {:?}", ev),
+            }
+        };
+
+        // no frames before the first tile list OBU
+        assert_eq!(tile_list(&mut parser), [None, None, None]);
+
+        // frames decoded before the first tile list OBU are anchor frames
+        decode_frame(&mut parser);
+        decode_frame(&mut parser);
+        assert_eq!(tile_list(&mut parser), [Some(0), Some(1), None]);
+        // subsequent tile list OBUs of the same group refer to the same anchor frames
+        assert_eq!(tile_list(&mut parser), [Some(0), Some(1), None]);
+
+        // frames decoded after a tile list OBU start a new group
+        for _ in 0..3 {
+            decode_frame(&mut parser);
+        }
+        assert_eq!(parser.seq.anchor_frames, [0, 1]);
+        assert_eq!(tile_list(&mut parser), [Some(2), Some(3), Some(4)]);
+
+        // frame header without tile data is not an anchor frame
+        parser.parse_obu(&obu_header(obu::OBU_TEMPORAL_DELIMITER, None), &[]);
+        parser.parse_obu(
+            &obu_header(obu::OBU_FRAME_HEADER, None),
+            &[0x00, 0x00, 0x20],
+        );
+        assert_eq!(tile_list(&mut parser), [Some(2), Some(3), Some(4)]);
+    }
+
+    #[test]
+    fn redundant_frame_header() {
+        let mut parser = StreamParser::new(0);
+        parser.parse_obu(
+            &obu_header(obu::OBU_SEQUENCE_HEADER, None),
+            &still_picture_sequence_header(),
+        );
+        // uncompressed_header() of 18 bits and trailing_bits()
+        let header = [0x00, 0x00, 0x20];
+        let events = parser.parse_obu(&obu_header(obu::OBU_FRAME_HEADER, None), &header);
+        assert!(matches!(
+            events[..],
+            [Event::FrameHeader {
+                decode_order: 0,
+                ..
+            }]
+        ));
+        assert!(parser.seq.seen_frame_header);
+
+        // frame_header_copy() is compared up to the frame header size
+        let redundant = obu_header(obu::OBU_REDUNDANT_FRAME_HEADER, None);
+        for copy in &[[0x00, 0x00, 0x20], [0x00, 0x00, 0x3f]] {
+            let events = parser.parse_obu(&redundant, copy);
+            assert!(matches!(events[..], [Event::FrameHeaderCopy]));
+        }
+        let events = parser.parse_obu(&redundant, &[0x80, 0x00, 0x20]);
+        assert!(matches!(
+            events[..],
+            [Event::Warning(Warning::FrameHeaderCopyMismatch)]
+        ));
+        let events = parser.parse_obu(&redundant, &[0x00]);
+        assert!(matches!(
+            events[..],
+            [Event::Warning(Warning::Invalid {
+                syntax: "FrameHeader",
+                ..
+            })]
+        ));
+
+        // the last tile group resets SeenFrameHeader
+        let tile_group = obu_header(obu::OBU_TILE_GROUP, None);
+        let events = parser.parse_obu(&tile_group, &[0xaa]);
+        assert!(matches!(events[..], [Event::TileGroup(_)]));
+        assert!(!parser.seq.seen_frame_header);
+        let events = parser.parse_obu(&tile_group, &[0xaa]);
+        assert!(matches!(
+            events[..],
+            [Event::Warning(Warning::NoFrameHeader)]
+        ));
+
+        // redundant frame header is parsed as new frame header after temporal delimiter
+        parser.parse_obu(&obu_header(obu::OBU_TEMPORAL_DELIMITER, None), &[]);
+        let events = parser.parse_obu(&redundant, &header);
+        assert!(matches!(
+            events[..],
+            [Event::FrameHeader {
+                decode_order: 1,
+                ..
+            }]
+        ));
+    }
+}
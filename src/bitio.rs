@@ -1,23 +1,23 @@
-use std::io;
+use crate::obu::{ParseError, ParseErrorKind};
 
-/// numeric cast helper (u32 as T)
-pub trait FromU32 {
-    fn from_u32(v: u32) -> Self;
+/// numeric cast helper (u64 as T)
+pub trait FromU64 {
+    fn from_u64(v: u64) -> Self;
 }
 
-impl FromU32 for bool {
+impl FromU64 for bool {
     #[inline]
-    fn from_u32(v: u32) -> Self {
+    fn from_u64(v: u64) -> Self {
         v != 0
     }
 }
 
-macro_rules! impl_from_u32 {
+macro_rules! impl_from_u64 {
     ($($ty:ty)*) => {
         $(
-            impl FromU32 for $ty {
+            impl FromU64 for $ty {
             #[inline]
-                fn from_u32(v: u32) -> $ty {
+                fn from_u64(v: u64) -> $ty {
                     v as $ty
                 }
             }
@@ -25,74 +25,162 @@ macro_rules! impl_from_u32 {
     }
 }
 
-impl_from_u32!(u8 u16 u32 u64 usize);
+impl_from_u64!(u8 u16 u32 u64 usize);
 
 ///
-/// Bitwise reader
+/// Bitwise reader on byte slice
 ///
-pub struct BitReader<R> {
-    inner: R,
-    bbuf: u8,
-    bpos: u8,
+pub struct BitReader<'a> {
+    data: &'a [u8],
+    bytepos: usize,  // next byte position to be loaded into cache
+    cache: u64,      // unread bits (MSB aligned)
+    cache_bits: u32, // number of valid bits in cache
 }
 
-impl<R: io::Read> BitReader<R> {
-    pub fn new(inner: R) -> BitReader<R> {
+impl<'a> BitReader<'a> {
+    pub fn new(data: &'a [u8]) -> BitReader<'a> {
         BitReader {
-            inner,
-            bbuf: 0,
-            bpos: 0,
+            data,
+            bytepos: 0,
+            cache: 0,
+            cache_bits: 0,
         }
     }
 
-    /// read_bit: read 1 bit
-    pub fn read_bit(&mut self) -> Option<u8> {
-        if self.bpos == 0 {
-            let mut bbuf = [0; 1];
-            match self.inner.read(&mut bbuf) {
-                Ok(0) | Err(_) => return None, // EOF or IOErr
-                Ok(n) => assert_eq!(n, 1),
-            }
-            self.bbuf = bbuf[0];
-            self.bpos = 8;
+    /// current bit position
+    pub fn position(&self) -> u64 {
+        self.bytepos as u64 * 8 - self.cache_bits as u64
+    }
+
+    /// number of unread bits
+    pub fn remaining_bits(&self) -> u64 {
+        (self.data.len() - self.bytepos) as u64 * 8 + self.cache_bits as u64
+    }
+
+    /// parse error at the current bit position
+    pub fn error(&self, kind: ParseErrorKind) -> ParseError {
+        ParseError::new(kind, self.position())
+    }
+
+    // load bytes into cache while there is room for 8 bits
+    #[inline]
+    fn refill(&mut self) {
+        while self.cache_bits <= 56 && self.bytepos < self.data.len() {
+            self.cache |= (self.data[self.bytepos] as u64) << (56 - self.cache_bits);
+            self.bytepos += 1;
+            self.cache_bits += 8;
+        }
+    }
+
+    // read n-bits (n <= 32) from cache, caller should check remaining bits
+    #[inline]
+    fn read_bits(&mut self, n: u32) -> u64 {
+        if n == 0 {
+            return 0;
+        }
+        if self.cache_bits < n {
+            self.refill();
         }
-        self.bpos -= 1;
-        Some((self.bbuf >> self.bpos) & 1)
+        let v = self.cache >> (64 - n);
+        self.cache <<= n;
+        self.cache_bits -= n;
+        v
+    }
+
+    /// read_bit: read 1 bit
+    pub fn read_bit(&mut self) -> Result<u8, ParseError> {
+        self.f::<u8>(1)
+    }
+
+    /// byte_alignment(): skip to the next byte boundary
+    pub fn byte_alignment(&mut self) {
+        let n = self.cache_bits % 8;
+        self.cache <<= n;
+        self.cache_bits -= n;
     }
 
     /// f(n): read n-bits
-    pub fn f<T: FromU32>(&mut self, nbit: usize) -> Option<T> {
-        assert!(nbit <= 32);
-        let mut x: u32 = 0;
-        for _ in 0..nbit {
-            x = (x << 1) | self.read_bit()? as u32;
+    pub fn f<T: FromU64>(&mut self, nbit: usize) -> Result<T, ParseError> {
+        assert!(nbit <= 64);
+        if nbit as u64 > self.remaining_bits() {
+            return Err(self.error(ParseErrorKind::Truncated));
+        }
+        let x = if nbit > 32 {
+            let hi = self.read_bits(nbit as u32 - 32);
+            (hi << 32) | self.read_bits(32)
+        } else {
+            self.read_bits(nbit as u32)
+        };
+        Ok(FromU64::from_u64(x))
+    }
+
+    /// skip n-bytes from the byte aligned position
+    pub fn skip_bytes(&mut self, n: u32) -> Result<(), ParseError> {
+        let nbit = n as u64 * 8;
+        if nbit > self.remaining_bits() {
+            return Err(self.error(ParseErrorKind::Truncated));
         }
-        Some(FromU32::from_u32(x))
+        let pos = (self.position() + nbit) as usize / 8;
+        self.bytepos = pos;
+        self.cache = 0;
+        self.cache_bits = 0;
+        Ok(())
     }
 
     /// su(n)
-    pub fn su(&mut self, n: usize) -> Option<i32> {
+    pub fn su(&mut self, n: usize) -> Result<i32, ParseError> {
         let mut value = self.f::<u32>(n)? as i32;
         let sign_mask = 1 << (n - 1);
         if value & sign_mask != 0 {
             value -= 2 * sign_mask
         }
-        Some(value)
+        Ok(value)
     }
 
     /// ns(n)
-    pub fn ns(&mut self, n: u32) -> Option<u32> {
+    pub fn ns(&mut self, n: u32) -> Result<u32, ParseError> {
         let w = Self::floor_log2(n) + 1;
         let m = (1 << w) - n;
         let v = self.f::<u32>(w as usize - 1)?; // f(w - 1)
         if v < m {
-            return Some(v);
+            return Ok(v);
         }
         let extra_bit = self.f::<u32>(1)?; // f(1)
-        Some((v << 1) - m + extra_bit)
+        Ok((v << 1) - m + extra_bit)
+    }
+
+    /// le(n): n-bytes little-endian unsigned integer
+    pub fn le(&mut self, n: usize) -> Result<u32, ParseError> {
+        let mut t = 0;
+        for i in 0..n {
+            let byte = self.f::<u32>(8)?; // f(8)
+            t += byte << (i * 8);
+        }
+        Ok(t)
+    }
+
+    /// leb128(): return (Leb128Bytes, value)
+    pub fn leb128(&mut self) -> Result<(u32, u32), ParseError> {
+        let mut value: u64 = 0;
+        let mut leb128bytes = 0;
+        for i in 0..8 {
+            let leb128_byte = self.f::<u8>(8)?; // f(8)
+            value |= ((leb128_byte & 0x7f) as u64) << (i * 7);
+            leb128bytes += 1;
+            if (leb128_byte & 0x80) != 0x80 {
+                break;
+            }
+        }
+        if value > (1u64 << 32) - 1 {
+            return Err(self.error(ParseErrorKind::InvalidValue {
+                element: "leb128()",
+                value: value as i64,
+            }));
+        }
+        Ok((leb128bytes, value as u32))
     }
 
-    pub fn uvlc(&mut self) -> Option<u64> {
+    pub fn uvlc(&mut self) -> Result<u64, ParseError> {
         let mut leading_zeros = 0;
         loop {
             let done = self.read_bit()? > 0;
@@ -103,12 +191,12 @@ impl<R: io::Read> BitReader<R> {
         }
 
         if leading_zeros >= 32 {
-            return Some((1 << 32) - 1);
+            return Ok((1 << 32) - 1);
         }
 
         let value = self.f::<u64>(leading_zeros as usize)?;
 
-        Some(value + (1 << leading_zeros) - 1)
+        Ok(value + (1 << leading_zeros) - 1)
     }
 
     // FloorLog2(x)
@@ -121,3 +209,147 @@ impl<R: io::Read> BitReader<R> {
         s - 1
     }
 }
+
+/// pack (value, n-bits) fields into bytes (MSB first, zero padded), test vector helper
+#[cfg(test)]
+pub fn pack_bits(fields: &[(u64, usize)]) -> Vec<u8> {
+    let mut data = Vec::new();
+    let mut pos = 0;
+    for &(value, nbit) in fields {
+        for i in (0..nbit).rev() {
+            if pos % 8 == 0 {
+                data.push(0);
+            }
+            let bit = ((value >> i) & 1) as u8;
+            *data.last_mut().unwrap() |= bit << (7 - pos % 8);
+            pos += 1;
+        }
+    }
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DATA: [u8; 16] = [
+        0x01, 0x23, 0x45, 0x67, 0x89, 0xab, 0xcd, 0xef, 0xfe, 0xdc, 0xba, 0x98, 0x76, 0x54, 0x32,
+        0x10,
+    ];
+
+    #[test]
+    fn refill_across_cache_boundary() {
+        let mut br = BitReader::new(&DATA);
+        assert_eq!(br.f::<u8>(7), Ok(0x00));
+        assert_eq!(br.f::<u64>(64), Ok(0x91a2_b3c4_d5e6_f7ff));
+        assert_eq!(br.position(), 71);
+        assert_eq!(br.f::<u64>(33), Ok(0xdcba_9876));
+        assert_eq!(br.f::<u32>(24), Ok(0x54_3210));
+        assert_eq!(br.remaining_bits(), 0);
+        assert_eq!(
+            br.f::<u8>(1),
+            Err(ParseError::new(ParseErrorKind::Truncated, 128))
+        );
+    }
+
+    #[test]
+    fn f64_and_truncated() {
+        let mut br = BitReader::new(&DATA);
+        assert_eq!(br.f::<u64>(64), Ok(0x0123_4567_89ab_cdef));
+        assert_eq!(br.f::<u64>(64), Ok(0xfedc_ba98_7654_3210));
+
+        let mut br = BitReader::new(&DATA[..1]);
+        assert_eq!(
+            br.f::<u16>(9),
+            Err(ParseError::new(ParseErrorKind::Truncated, 0))
+        );
+        assert_eq!(br.f::<bool>(1), Ok(false));
+    }
+
+    #[test]
+    fn su() {
+        let mut br = BitReader::new(&[0b1111_0111, 0b1000_0000]);
+        assert_eq!(br.su(4), Ok(-1));
+        assert_eq!(br.su(4), Ok(7));
+        assert_eq!(br.su(7), Ok(-64));
+    }
+
+    #[test]
+    fn ns() {
+        // ns(5): w=3, m=3, "10" -> 2, "11"+"1" -> 4
+        let mut br = BitReader::new(&[0b1011_1000]);
+        assert_eq!(br.ns(5), Ok(2));
+        assert_eq!(br.ns(5), Ok(4));
+        assert_eq!(br.position(), 5);
+    }
+
+    #[test]
+    fn le() {
+        let mut br = BitReader::new(&[0x34, 0x12, 0xff, 0xff, 0xff, 0xff]);
+        assert_eq!(br.le(2), Ok(0x1234));
+        assert_eq!(br.le(4), Ok(0xffff_ffff));
+    }
+
+    #[test]
+    fn leb128() {
+        let mut br = BitReader::new(&[0xe5, 0x8e, 0x26]);
+        assert_eq!(br.leb128(), Ok((3, 624_485)));
+
+        // at most 8 bytes
+        let mut br = BitReader::new(&[0x80; 9]);
+        assert_eq!(br.leb128(), Ok((8, 0)));
+        assert_eq!(br.position(), 64);
+
+        let mut br = BitReader::new(&[0xff, 0xff, 0xff, 0xff, 0x1f]);
+        assert_eq!(
+            br.leb128().unwrap_err().kind,
+            ParseErrorKind::InvalidValue {
+                element: "leb128()",
+                value: 0x1_ffff_ffff,
+            }
+        );
+    }
+
+    #[test]
+    fn uvlc() {
+        // "1", "010", "011", "00100"
+        let mut br = BitReader::new(&[0b1010_0110, 0b0100_0000]);
+        assert_eq!(br.uvlc(), Ok(0));
+        assert_eq!(br.uvlc(), Ok(1));
+        assert_eq!(br.uvlc(), Ok(2));
+        assert_eq!(br.uvlc(), Ok(3));
+        assert_eq!(br.position(), 12);
+
+        // leadingZeros >= 32
+        let mut br = BitReader::new(&[0x00, 0x00, 0x00, 0x00, 0x80]);
+        assert_eq!(br.uvlc(), Ok((1 << 32) - 1));
+        assert_eq!(br.position(), 33);
+    }
+
+    #[test]
+    fn pack_bits_roundtrip() {
+        let data = pack_bits(&[(0b101, 3), (0x1234_5678_9abc, 48), (1, 1)]);
+        assert_eq!(data.len(), 7);
+        let mut br = BitReader::new(&data);
+        assert_eq!(br.f::<u8>(3), Ok(0b101));
+        assert_eq!(br.f::<u64>(48), Ok(0x1234_5678_9abc));
+        assert_eq!(br.f::<bool>(1), Ok(true));
+        assert_eq!(br.remaining_bits(), 4);
+    }
+
+    #[test]
+    fn byte_alignment() {
+        let mut br = BitReader::new(&DATA);
+        br.byte_alignment();
+        assert_eq!(br.position(), 0);
+        assert_eq!(br.f::<u8>(3), Ok(0));
+        br.byte_alignment();
+        assert_eq!(br.position(), 8);
+        assert_eq!(br.f::<u8>(8), Ok(0x23));
+        assert_eq!(br.f::<u64>(60), Ok(0x0456_789a_bcde_ffed));
+        br.byte_alignment();
+        assert_eq!(br.position(), 80);
+        assert_eq!(br.f::<u8>(8), Ok(0xba));
+        assert_eq!(br.remaining_bits(), 40);
+    }
+}
@@ -0,0 +1,534 @@
+//
+// ATSC A/53 closed captions (CEA-608/708 cc_data) in ITU-T T.35 metadata OBU
+//
+// https://www.atsc.org/atsc-documents/a53-atsc-digital-television-standard/
+//
+use crate::bitio::BitReader;
+use crate::obu;
+use std::fmt::Write;
+
+pub const ITU_T_T35_COUNTRY_CODE_USA: u8 = 0xB5;
+pub const ITU_T_T35_PROVIDER_CODE_ATSC: u16 = 0x0031;
+pub const ATSC_USER_IDENTIFIER: [u8; 4] = *b"GA94";
+pub const USER_DATA_TYPE_CC_DATA: u8 = 0x03;
+
+// cc_type (1: CEA-608 field 2, 2: DTVCC packet data, 3: DTVCC packet start)
+pub const CC_TYPE_NTSC_FIELD1: u8 = 0; // CEA-608 field 1 (CC1/CC2)
+
+///
+/// cc_data()
+///
+#[derive(Debug, Default)]
+pub struct CcData {
+    pub process_cc_data_flag: bool, // f(1)
+    pub additional_data_flag: bool, // f(1)
+    pub cc_count: u8,               // f(5)
+    pub em_data: u8,                // f(8)
+    pub triplets: Vec<CcTriplet>,
+}
+
+///
+/// cc_data triplet
+///
+#[derive(Debug, Default, Clone, Copy)]
+pub struct CcTriplet {
+    pub cc_valid: bool, // f(1)
+    pub cc_type: u8,    // f(2)
+    pub cc_data_1: u8,  // f(8)
+    pub cc_data_2: u8,  // f(8)
+}
+
+/// return true if T.35 metadata carries ATSC A/53 cc_data
+pub fn is_a53_cc_data(t35: &obu::ItutT35Metadata) -> bool {
+    let p = &t35.itu_t_t35_payload_bytes;
+    t35.itu_t_t35_country_code == ITU_T_T35_COUNTRY_CODE_USA
+        && p.len() >= 7
+        && ((p[0] as u16) << 8 | p[1] as u16) == ITU_T_T35_PROVIDER_CODE_ATSC
+        && p[2..6] == ATSC_USER_IDENTIFIER
+        && p[6] == USER_DATA_TYPE_CC_DATA
+}
+
+///
+/// parse ATSC A/53 cc_data() from ITU-T T.35 metadata
+///
+pub fn parse_a53_cc_data(t35: &obu::ItutT35Metadata) -> Option<CcData> {
+    if !is_a53_cc_data(t35) {
+        return None;
+    }
+    // skip itu_t_t35_terminal_provider_code, user_identifier and user_data_type_code
+    let mut br = BitReader::new(&t35.itu_t_t35_payload_bytes[7..]);
+    let mut cc = CcData::default();
+
+    let _reserved = br.f::<u8>(1).ok()?; // f(1)
+    cc.process_cc_data_flag = br.f::<bool>(1).ok()?; // f(1)
+    cc.additional_data_flag = br.f::<bool>(1).ok()?; // f(1)
+    cc.cc_count = br.f::<u8>(5).ok()?; // f(5)
+    cc.em_data = br.f::<u8>(8).ok()?; // f(8)
+    for _ in 0..cc.cc_count {
+        let _one_bit = br.f::<u8>(5).ok()?; // f(5)
+        let triplet = CcTriplet {
+            cc_valid: br.f::<bool>(1).ok()?, // f(1)
+            cc_type: br.f::<u8>(2).ok()?,    // f(2)
+            cc_data_1: br.f::<u8>(8).ok()?,  // f(8)
+            cc_data_2: br.f::<u8>(8).ok()?,  // f(8)
+        };
+        cc.triplets.push(triplet);
+    }
+    // marker_bits f(8)
+
+    Some(cc)
+}
+
+/// CEA-608 field 1 byte pairs of cc_data (including padding)
+pub fn field1_pairs(cc: &CcData) -> Vec<(u8, u8)> {
+    cc.triplets
+        .iter()
+        .filter(|t| t.cc_valid && t.cc_type == CC_TYPE_NTSC_FIELD1)
+        .map(|t| (t.cc_data_1, t.cc_data_2))
+        .collect()
+}
+
+// CEA-608 character sets
+const BASIC_CHARS: [char; 96] = [
+    ' ', '!', '"', '#', '$', '%', '&', '\'', '(', ')', 'á', '+', ',', '-', '.', '/', //
+    '0', '1', '2', '3', '4', '5', '6', '7', '8', '9', ':', ';', '<', '=', '>', '?', //
+    '@', 'A', 'B', 'C', 'D', 'E', 'F', 'G', 'H', 'I', 'J', 'K', 'L', 'M', 'N', 'O', //
+    'P', 'Q', 'R', 'S', 'T', 'U', 'V', 'W', 'X', 'Y', 'Z', '[', 'é', ']', 'í', 'ó', //
+    'ú', 'a', 'b', 'c', 'd', 'e', 'f', 'g', 'h', 'i', 'j', 'k', 'l', 'm', 'n', 'o', //
+    'p', 'q', 'r', 's', 't', 'u', 'v', 'w', 'x', 'y', 'z', 'ç', '÷', 'Ñ', 'ñ', '█', //
+];
+const SPECIAL_CHARS: [char; 16] = [
+    '®', '°', '½', '¿', '™', '¢', '£', '♪', 'à', ' ', 'è', 'â', 'ê', 'î', 'ô', 'û',
+];
+const EXTENDED_CHARS_SPANISH_FRENCH: [char; 32] = [
+    'Á', 'É', 'Ó', 'Ú', 'Ü', 'ü', '‘', '¡', '*', '\'', '—', '©', '℠', '•', '“', '”', //
+    'À', 'Â', 'Ç', 'È', 'Ê', 'Ë', 'ë', 'Î', 'Ï', 'ï', 'Ô', 'Ù', 'ù', 'Û', '«', '»', //
+];
+const EXTENDED_CHARS_PORTUGUESE_GERMAN: [char; 32] = [
+    'Ã', 'ã', 'Í', 'Ì', 'ì', 'Ò', 'ò', 'Õ', 'õ', '{', '}', '\\', '^', '_', '|', '~', //
+    'Ä', 'ä', 'Ö', 'ö', 'ß', '¥', '¤', '¦', 'Å', 'å', 'Ø', 'ø', '┌', '┐', '└', '┘', //
+];
+// PAC row number for (b1 & 0x07, b2 & 0x20)
+const PAC_ROWS: [[usize; 2]; 8] = [
+    [10, 10],
+    [0, 1],
+    [2, 3],
+    [11, 12],
+    [13, 14],
+    [4, 5],
+    [6, 7],
+    [8, 9],
+];
+const NUM_ROWS: usize = 15;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CaptionMode {
+    PopOn,
+    RollUp(usize),
+    PaintOn,
+    Text,
+}
+
+///
+/// CEA-608 caption decoder (CC1 channel, text only)
+///
+pub struct Cea608Decoder {
+    mode: CaptionMode,
+    displayed: Vec<String>,
+    non_displayed: Vec<String>,
+    row: usize,
+    channel: u8,
+    last_control: Option<(u8, u8)>,
+}
+
+impl Default for Cea608Decoder {
+    fn default() -> Self {
+        Cea608Decoder {
+            mode: CaptionMode::PopOn,
+            displayed: vec![String::new(); NUM_ROWS],
+            non_displayed: vec![String::new(); NUM_ROWS],
+            row: NUM_ROWS - 1,
+            channel: 1,
+            last_control: None,
+        }
+    }
+}
+
+impl Cea608Decoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// displayed caption text
+    pub fn text(&self) -> String {
+        let rows: Vec<&str> = self
+            .displayed
+            .iter()
+            .map(|r| r.trim_end())
+            .filter(|r| !r.is_empty())
+            .collect();
+        rows.join("\n")
+    }
+
+    fn memory(&mut self) -> &mut Vec<String> {
+        match self.mode {
+            CaptionMode::PopOn => &mut self.non_displayed,
+            _ => &mut self.displayed,
+        }
+    }
+
+    fn put_char(&mut self, c: char) {
+        if self.mode == CaptionMode::Text {
+            return;
+        }
+        let row = self.row;
+        self.memory()[row].push(c);
+    }
+
+    fn backspace(&mut self) {
+        let row = self.row;
+        self.memory()[row].pop();
+    }
+
+    fn carriage_return(&mut self) {
+        if let CaptionMode::RollUp(rows) = self.mode {
+            let top = (self.row + 1).saturating_sub(rows);
+            for r in 0..NUM_ROWS {
+                if r < top || r > self.row {
+                    self.displayed[r].clear();
+                }
+            }
+            for r in top..self.row {
+                self.displayed[r] = self.displayed[r + 1].clone();
+            }
+            self.displayed[self.row].clear();
+        }
+    }
+
+    fn control_code(&mut self, b1: u8, b2: u8) {
+        match (b1, b2) {
+            // miscellaneous control codes
+            (0x14, 0x20..=0x2F) | (0x15, 0x20..=0x2F) => match b2 {
+                0x20 => self.mode = CaptionMode::PopOn, // RCL
+                0x21 => self.backspace(),               // BS
+                0x24 => {
+                    // DER
+                    let row = self.row;
+                    self.memory()[row].clear();
+                }
+                0x25..=0x27 => {
+                    // RU2, RU3, RU4
+                    let rows = (b2 - 0x25 + 2) as usize;
+                    if !matches!(self.mode, CaptionMode::RollUp(_)) {
+                        self.displayed = vec![String::new(); NUM_ROWS];
+                        self.row = NUM_ROWS - 1;
+                    }
+                    self.mode = CaptionMode::RollUp(rows);
+                }
+                0x29 => self.mode = CaptionMode::PaintOn, // RDC
+                0x2A | 0x2B => self.mode = CaptionMode::Text, // TR, RTD
+                0x2C => self.displayed = vec![String::new(); NUM_ROWS], // EDM
+                0x2D => self.carriage_return(),           // CR
+                0x2E => self.non_displayed = vec![String::new(); NUM_ROWS], // ENM
+                0x2F => {
+                    // EOC
+                    ::std::mem::swap(&mut self.displayed, &mut self.non_displayed);
+                    self.mode = CaptionMode::PopOn;
+                }
+                _ => {}
+            },
+            // tab offsets
+            (0x17, 0x21..=0x23) => {
+                for _ in 0..(b2 - 0x20) {
+                    self.put_char(' ');
+                }
+            }
+            // mid-row codes (occupy one space)
+            (0x11, 0x20..=0x2F) => self.put_char(' '),
+            // special characters
+            (0x11, 0x30..=0x3F) => self.put_char(SPECIAL_CHARS[(b2 - 0x30) as usize]),
+            // extended characters replace the preceding character
+            (0x12, 0x20..=0x3F) => {
+                self.backspace();
+                self.put_char(EXTENDED_CHARS_SPANISH_FRENCH[(b2 - 0x20) as usize]);
+            }
+            (0x13, 0x20..=0x3F) => {
+                self.backspace();
+                self.put_char(EXTENDED_CHARS_PORTUGUESE_GERMAN[(b2 - 0x20) as usize]);
+            }
+            // preamble address codes
+            (0x10..=0x17, 0x40..=0x7F) => {
+                let row = PAC_ROWS[(b1 & 0x07) as usize][((b2 & 0x20) >> 5) as usize];
+                if let CaptionMode::RollUp(_) = self.mode {
+                    if row != self.row {
+                        // move roll-up window to the new base row
+                        let rows = self.displayed.clone();
+                        self.displayed = vec![String::new(); NUM_ROWS];
+                        for (r, text) in rows.into_iter().enumerate() {
+                            let dst = r as isize + row as isize - self.row as isize;
+                            if dst >= 0 && (dst as usize) < NUM_ROWS {
+                                self.displayed[dst as usize] = text;
+                            }
+                        }
+                    }
+                }
+                self.row = row;
+                if self.mode != CaptionMode::Text {
+                    let row = self.row;
+                    let memory = self.memory();
+                    if !memory[row].is_empty() && !memory[row].ends_with(' ') {
+                        memory[row].push(' ');
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// decode one byte pair of CEA-608 field 1
+    pub fn decode(&mut self, cc_data_1: u8, cc_data_2: u8) {
+        // remove odd parity bit
+        let (b1, b2) = (cc_data_1 & 0x7F, cc_data_2 & 0x7F);
+        if b1 == 0 && b2 == 0 {
+            return; // padding
+        }
+        if (0x10..=0x1F).contains(&b1) {
+            // control codes are transmitted twice
+            if self.last_control == Some((b1, b2)) {
+                self.last_control = None;
+                return;
+            }
+            self.last_control = Some((b1, b2));
+            self.channel = if b1 & 0x08 != 0 { 2 } else { 1 };
+            if self.channel == 1 {
+                self.control_code(b1 & !0x08, b2);
+            }
+            return;
+        }
+        self.last_control = None;
+        if self.channel != 1 {
+            return;
+        }
+        for &b in &[b1, b2] {
+            if b >= 0x20 {
+                self.put_char(BASIC_CHARS[(b - 0x20) as usize]);
+            }
+        }
+    }
+}
+
+/// time of frame in milliseconds
+fn frame_time_ms(frame: i64, frame_rate: (u32, u32)) -> i64 {
+    frame * 1000 * frame_rate.1 as i64 / frame_rate.0 as i64
+}
+
+/// format time as SRT timestamp "HH:MM:SS,mmm"
+fn srt_timestamp(ms: i64) -> String {
+    format!(
+        "{:02}:{:02}:{:02},{:03}",
+        ms / 3_600_000,
+        ms / 60_000 % 60,
+        ms / 1000 % 60,
+        ms % 1000
+    )
+}
+
+///
+/// export CEA-608 CC1 captions in SubRip(SRT) format
+///
+/// `frames` is a list of (frame index in presentation order, cc_data),
+/// `frame_rate` is (numerator, denominator) of frames per second.
+///
+pub fn export_srt(frames: &[(i64, CcData)], frame_rate: (u32, u32)) -> String {
+    let mut decoder = Cea608Decoder::new();
+    let mut srt = String::new();
+    let mut cue_num = 0;
+    let mut current: Option<(i64, String)> = None;
+
+    let mut emit = |srt: &mut String, start: i64, end: i64, text: &str| {
+        cue_num += 1;
+        let _ = writeln!(srt, "{}", cue_num);
+        let _ = writeln!(
+            srt,
+            "{} --> {}",
+            srt_timestamp(frame_time_ms(start, frame_rate)),
+            srt_timestamp(frame_time_ms(end, frame_rate))
+        );
+        let _ = writeln!(srt, "{}\n", text);
+    };
+
+    let mut last_frame = 0;
+    for &(frame, ref cc) in frames {
+        for (b1, b2) in field1_pairs(cc) {
+            decoder.decode(b1, b2);
+        }
+        let text = decoder.text();
+        let changed = match current {
+            Some((_, ref cur)) => *cur != text,
+            None => !text.is_empty(),
+        };
+        if changed {
+            if let Some((start, ref cur)) = current {
+                emit(&mut srt, start, frame, cur);
+            }
+            current = if text.is_empty() {
+                None
+            } else {
+                Some((frame, text))
+            };
+        }
+        last_frame = frame;
+    }
+    if let Some((start, ref cur)) = current {
+        emit(&mut srt, start, last_frame + 1, cur);
+    }
+    srt
+}
+
+///
+/// export CEA-608 field 1 byte pairs in Scenarist(SCC) format
+///
+/// Timecodes are non-drop-frame with the nominal integer frame rate.
+///
+pub fn export_scc(frames: &[(i64, CcData)], frame_rate: (u32, u32)) -> String {
+    let fps = ((frame_rate.0 + frame_rate.1 / 2) / frame_rate.1).max(1) as i64;
+    let mut scc = String::from("Scenarist_SCC V1.0\n");
+    for &(frame, ref cc) in frames {
+        let pairs: Vec<String> = field1_pairs(cc)
+            .iter()
+            .filter(|&&(b1, b2)| b1 & 0x7F != 0 || b2 & 0x7F != 0)
+            .map(|&(b1, b2)| format!("{:02x}{:02x}", b1, b2))
+            .collect();
+        if pairs.is_empty() {
+            continue;
+        }
+        let seconds = frame / fps;
+        let _ = writeln!(
+            scc,
+            "\n{:02}:{:02}:{:02}:{:02}\t{}",
+            seconds / 3600,
+            seconds / 60 % 60,
+            seconds % 60,
+            frame % fps,
+            pairs.join(" ")
+        );
+    }
+    scc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cc_data(pairs: &[(u8, u8)]) -> CcData {
+        CcData {
+            process_cc_data_flag: true,
+            cc_count: pairs.len() as u8,
+            triplets: pairs
+                .iter()
+                .map(|&(cc_data_1, cc_data_2)| CcTriplet {
+                    cc_valid: true,
+                    cc_type: CC_TYPE_NTSC_FIELD1,
+                    cc_data_1,
+                    cc_data_2,
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    fn decode(decoder: &mut Cea608Decoder, pairs: &[(u8, u8)]) {
+        for &(b1, b2) in pairs {
+            decoder.decode(b1, b2);
+        }
+    }
+
+    #[test]
+    fn a53_cc_data() {
+        let mut t35 = obu::ItutT35Metadata {
+            itu_t_t35_country_code: ITU_T_T35_COUNTRY_CODE_USA,
+            itu_t_t35_country_code_extension_byte: None,
+            itu_t_t35_payload_bytes: vec![
+                0x00, 0x31, b'G', b'A', b'9', b'4', 0x03, // provider code, GA94, cc_data
+                0xc2, 0xff, // process_cc_data_flag=1, cc_count=2, em_data
+                0xfc, 0x94, 0x20, // cc_valid=1, cc_type=0 (field 1)
+                0xfd, 0x80, 0x80, // cc_valid=1, cc_type=1 (field 2)
+                0xff, // marker_bits
+            ],
+        };
+        let cc = parse_a53_cc_data(&t35).unwrap();
+        assert!(cc.process_cc_data_flag);
+        assert!(!cc.additional_data_flag);
+        assert_eq!((cc.cc_count, cc.em_data), (2, 0xff));
+        assert_eq!(cc.triplets[1].cc_type, 1);
+        assert_eq!(field1_pairs(&cc), [(0x94, 0x20)]);
+
+        t35.itu_t_t35_payload_bytes.truncate(12);
+        assert!(parse_a53_cc_data(&t35).is_none());
+        t35.itu_t_t35_country_code = 0x26;
+        assert!(!is_a53_cc_data(&t35));
+    }
+
+    #[test]
+    fn cea608_pop_on() {
+        let mut decoder = Cea608Decoder::new();
+        // RCL, RCL (redundant), PAC row 14, "HI"
+        decode(
+            &mut decoder,
+            &[(0x94, 0x20), (0x94, 0x20), (0x94, 0x70), (0xc8, 0x49)],
+        );
+        assert_eq!(decoder.text(), "");
+        // EOC, EOC (redundant)
+        decode(&mut decoder, &[(0x94, 0x2f), (0x94, 0x2f)]);
+        assert_eq!(decoder.text(), "HI");
+        // EDM
+        decode(&mut decoder, &[(0x94, 0x2c)]);
+        assert_eq!(decoder.text(), "");
+    }
+
+    #[test]
+    fn cea608_roll_up() {
+        let mut decoder = Cea608Decoder::new();
+        // RU2, "AB", CR, "CD"
+        decode(
+            &mut decoder,
+            &[(0x94, 0x25), (0x41, 0x42), (0x94, 0x2d), (0x43, 0x44)],
+        );
+        assert_eq!(decoder.text(), "AB\nCD");
+        // CR, "EF"
+        decode(&mut decoder, &[(0x94, 0x2d), (0x45, 0x46)]);
+        assert_eq!(decoder.text(), "CD\nEF");
+    }
+
+    #[test]
+    fn cea608_special_characters() {
+        let mut decoder = Cea608Decoder::new();
+        // RU2, special character, "a", extended character replaces "a"
+        decode(
+            &mut decoder,
+            &[(0x94, 0x25), (0x11, 0x37), (0x61, 0x00), (0x12, 0x21)],
+        );
+        assert_eq!(decoder.text(), "♪É");
+        // CC2 channel is ignored
+        decode(&mut decoder, &[(0x1c, 0x2d), (0x41, 0x41)]);
+        assert_eq!(decoder.text(), "♪É");
+    }
+
+    #[test]
+    fn srt() {
+        let frames = vec![
+            (0, cc_data(&[(0x94, 0x20), (0x94, 0x70)])),
+            (1, cc_data(&[(0xc8, 0x49), (0x94, 0x2f)])),
+            (30, cc_data(&[(0x94, 0x2c)])),
+            (31, cc_data(&[(0x80, 0x80)])),
+        ];
+        assert_eq!(
+            export_srt(&frames, (30, 1)),
+            "1\n00:00:00,033 --> 00:00:01,000\nHI\n\n"
+        );
+        assert_eq!(
+            export_scc(&frames, (30, 1)),
+            "Scenarist_SCC V1.0\n\n00:00:00:00\t9420 9470\n\n00:00:00:01\tc849 942f\n\n00:00:01:00\t942c\n"
+        );
+    }
+}
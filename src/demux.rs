@@ -0,0 +1,710 @@
+//
+// Container agnostic demuxer for AV1 temporal units
+//
+use crate::annexb;
+use crate::ivf;
+use crate::mkv;
+use crate::mp4;
+use crate::obu;
+use crate::{probe_fileformat, FileFormat, FCC_AV01};
+use hex;
+use std::io;
+use std::io::{Read, SeekFrom};
+
+///
+/// Temporal unit
+///
+#[derive(Debug)]
+pub struct TemporalUnit {
+    pub data: Vec<u8>, // OBUs in low overhead bitstream format
+    pub pts: i64,      // presentation timestamp
+    pub duration: Option<u64>,
+    pub timescale: Option<(u32, u32)>, // timestamp units per second, None if pts is temporal unit number
+    pub is_sync: bool,                 // sync sample (random access point)
+}
+
+///
+/// Track information
+///
+#[derive(Debug)]
+pub struct TrackInfo {
+    pub format: FileFormat,
+    pub codec: String, // codec identifier in container, empty if no container
+    pub size: Option<(u32, u32)>, // (width, height) [pel]
+    pub timescale: Option<(u32, u32)>, // timestamp units per second
+    pub length: Option<u64>, // number of temporal units declared in container
+}
+
+///
+/// Demuxer of AV1 temporal units
+///
+pub trait Demuxer {
+    /// read next temporal unit, return None at the end of stream
+    fn next_temporal_unit(&mut self) -> io::Result<Option<TemporalUnit>>;
+
+    /// AV1CodecConfigurationRecord in container
+    fn codec_config(&self) -> Option<&mp4::AV1CodecConfigurationBox>;
+
+    /// track information
+    fn track_info(&self) -> &TrackInfo;
+}
+
+///
+/// open demuxer for the probed file format
+///
+/// Return `io::ErrorKind::InvalidData` error if the file does not contain AV1 track.
+///
+pub fn open_any<'a, R: io::Read + io::Seek + 'a>(
+    mut reader: R,
+) -> io::Result<Box<dyn Demuxer + 'a>> {
+    let fmt = probe_fileformat(&mut reader)?;
+    reader.seek(SeekFrom::Start(0))?;
+    let demuxer: Box<dyn Demuxer + 'a> = match fmt {
+        FileFormat::IVF => Box::new(IvfDemuxer::new(reader)?),
+        FileFormat::WebM => Box::new(MkvDemuxer::new(reader)?),
+        FileFormat::MP4 => Box::new(Mp4Demuxer::new(reader)?),
+        FileFormat::Bitstream => Box::new(ObuDemuxer::new(reader)),
+        FileFormat::AnnexB => Box::new(AnnexBDemuxer::new(reader)),
+    };
+    Ok(demuxer)
+}
+
+fn invalid_data<E: Into<Box<dyn std::error::Error + Send + Sync>>>(err: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}
+
+/// read n-bytes from reader
+fn read_bytes<R: io::Read>(reader: &mut R, n: u64) -> io::Result<Vec<u8>> {
+    let mut data = Vec::new();
+    reader.take(n).read_to_end(&mut data)?;
+    if data.len() as u64 != n {
+        return Err(io::Error::from(io::ErrorKind::UnexpectedEof));
+    }
+    Ok(data)
+}
+
+/// return true if the temporal unit has sequence header and its first frame is shown key frame
+fn is_sync_sample(data: &[u8]) -> bool {
+    let mut reduced_still_picture_header = None;
+    for obu_ref in obu::ObuIter::new(data) {
+        let obu_ref = match obu_ref {
+            Ok(obu_ref) => obu_ref,
+            Err(_) => break,
+        };
+        let b0 = obu_ref.payload.first().cloned().unwrap_or(0);
+        match obu_ref.obu.obu_type {
+            obu::OBU_SEQUENCE_HEADER => {
+                // seq_profile f(3), still_picture f(1), reduced_still_picture_header f(1)
+                reduced_still_picture_header = Some((b0 >> 3) & 1 == 1);
+            }
+            obu::OBU_FRAME_HEADER | obu::OBU_FRAME => {
+                return match reduced_still_picture_header {
+                    Some(true) => true,
+                    Some(false) => {
+                        // show_existing_frame f(1), frame_type f(2), show_frame f(1)
+                        let show_existing_frame = b0 >> 7;
+                        let frame_type = (b0 >> 5) & 0b11;
+                        let show_frame = (b0 >> 4) & 1;
+                        show_existing_frame == 0 && frame_type == obu::KEY_FRAME && show_frame == 1
+                    }
+                    None => false,
+                };
+            }
+            _ => {}
+        }
+    }
+    false
+}
+
+///
+/// IVF demuxer
+///
+pub struct IvfDemuxer<R> {
+    reader: R,
+    info: TrackInfo,
+    next_frame: Option<ivf::IvfFrame>, // frame header of the next temporal unit
+}
+
+impl<R: io::Read> IvfDemuxer<R> {
+    pub fn new(mut reader: R) -> io::Result<Self> {
+        let mut ivf_header = [0; ivf::IVF_HEADER_SIZE];
+        reader.read_exact(&mut ivf_header)?;
+        let hdr = ivf::parse_ivf_header(&ivf_header).map_err(invalid_data)?;
+        if hdr.codec != FCC_AV01 {
+            return Err(invalid_data(format!(
+                "unsupport codec(0x{})",
+                hex::encode_upper(hdr.codec)
+            )));
+        }
+        let info = TrackInfo {
+            format: FileFormat::IVF,
+            codec: String::from_utf8_lossy(&hdr.codec).into_owned(),
+            size: Some((hdr.width as u32, hdr.height as u32)),
+            timescale: Some((hdr.timescale_num, hdr.timescale_den)),
+            length: Some(hdr.length as u64),
+        };
+        let next_frame = ivf::parse_ivf_frame(&mut reader).ok();
+        Ok(IvfDemuxer {
+            reader,
+            info,
+            next_frame,
+        })
+    }
+}
+
+impl<R: io::Read> Demuxer for IvfDemuxer<R> {
+    fn next_temporal_unit(&mut self) -> io::Result<Option<TemporalUnit>> {
+        let frame = match self.next_frame.take() {
+            Some(frame) => frame,
+            None => return Ok(None),
+        };
+        let data = read_bytes(&mut self.reader, frame.size as u64)?;
+        self.next_frame = ivf::parse_ivf_frame(&mut self.reader).ok();
+        Ok(Some(TemporalUnit {
+            is_sync: is_sync_sample(&data),
+            data,
+            pts: frame.pts as i64,
+            duration: self
+                .next_frame
+                .as_ref()
+                .and_then(|next| next.pts.checked_sub(frame.pts)),
+            timescale: self.info.timescale,
+        }))
+    }
+
+    fn codec_config(&self) -> Option<&mp4::AV1CodecConfigurationBox> {
+        None
+    }
+
+    fn track_info(&self) -> &TrackInfo {
+        &self.info
+    }
+}
+
+///
+/// Matroska/WebM demuxer
+///
+pub struct MkvDemuxer<R> {
+    reader: R,
+    webm: mkv::Matroska,
+    track_num: u64,
+    info: TrackInfo,
+    av1config: Option<mp4::AV1CodecConfigurationBox>, // CodecPrivate
+    duration: Option<u64>,                            // DefaultDuration in timescale
+}
+
+impl<R: io::Read + io::Seek> MkvDemuxer<R> {
+    pub fn new(mut reader: R) -> io::Result<Self> {
+        let webm = mkv::open_mkvfile(&mut reader)?;
+        let codec_id = mkv::CODEC_V_AV1;
+        let track_num = webm.find_track(codec_id).ok_or_else(|| {
+            invalid_data(format!("Matroska/WebM \"{}\" codec not found", codec_id))
+        })?;
+        let timecode_scale = webm.get_timecodescale();
+        if timecode_scale == 0 || timecode_scale > u32::MAX as u64 {
+            return Err(invalid_data(format!(
+                "invalid Matroska TimecodeScale={}",
+                timecode_scale
+            )));
+        }
+        let info = TrackInfo {
+            format: FileFormat::WebM,
+            codec: codec_id.to_owned(),
+            size: webm
+                .get_videosetting(track_num)
+                .map(|video| (video.pixel_width as u32, video.pixel_height as u32)),
+            timescale: Some((1_000_000_000, timecode_scale as u32)),
+            length: None,
+        };
+        // CodecPrivate is AV1CodecConfigurationRecord
+        let av1config = webm
+            .get_codecprivate(track_num)
+            .and_then(|data| mp4::read_av1codecconfig(data, data.len() as u64).ok());
+        let duration = webm
+            .get_defaultduration(track_num)
+            .and_then(|duration| duration.checked_div(timecode_scale));
+        Ok(MkvDemuxer {
+            reader,
+            webm,
+            track_num,
+            info,
+            av1config,
+            duration,
+        })
+    }
+}
+
+impl<R: io::Read + io::Seek> Demuxer for MkvDemuxer<R> {
+    fn next_temporal_unit(&mut self) -> io::Result<Option<TemporalUnit>> {
+        while let Some(block) = self.webm.next_block(&mut self.reader)? {
+            if block.track_num != self.track_num {
+                // skip non AV1 track data
+                continue;
+            }
+            let data = read_bytes(&mut self.reader, block.size)?;
+            return Ok(Some(TemporalUnit {
+                data,
+                pts: block.timecode,
+                duration: self.duration,
+                timescale: self.info.timescale,
+                is_sync: block.flags & mkv::BLOCK_FLAG_KEYFRAME != 0,
+            }));
+        }
+        Ok(None)
+    }
+
+    fn codec_config(&self) -> Option<&mp4::AV1CodecConfigurationBox> {
+        self.av1config.as_ref()
+    }
+
+    fn track_info(&self) -> &TrackInfo {
+        &self.info
+    }
+}
+
+///
+/// ISOBMFF/MP4 demuxer
+///
+pub struct Mp4Demuxer<R> {
+    reader: R,
+    mp4: mp4::IsoBmff,
+    info: TrackInfo,
+    sample_idx: usize, // index of the next sample
+}
+
+impl<R: io::Read + io::Seek> Mp4Demuxer<R> {
+    pub fn new(mut reader: R) -> io::Result<Self> {
+        let mp4 = mp4::open_mp4file(&mut reader)?;
+        let brand_av01 = mp4::FCC::from(mp4::BRAND_AV01);
+        if !mp4.get_filetype().compatible_brands.contains(&brand_av01) {
+            return Err(invalid_data(format!(
+                "ISOBMFF/MP4 {} brand not found",
+                brand_av01
+            )));
+        }
+        let info = match mp4.get_av1config() {
+            Some((av1se, _)) => TrackInfo {
+                format: FileFormat::MP4,
+                codec: brand_av01.to_string(),
+                size: Some((av1se.width as u32, av1se.height as u32)),
+                timescale: Some((mp4.get_timescale(), 1)),
+                length: Some(mp4.get_samples().len() as u64),
+            },
+            None => {
+                return Err(invalid_data(format!(
+                    "ISOBMFF/MP4 {} track not found",
+                    brand_av01
+                )))
+            }
+        };
+        Ok(Mp4Demuxer {
+            reader,
+            mp4,
+            info,
+            sample_idx: 0,
+        })
+    }
+}
+
+impl<R: io::Read + io::Seek> Demuxer for Mp4Demuxer<R> {
+    fn next_temporal_unit(&mut self) -> io::Result<Option<TemporalUnit>> {
+        let sample = match self.mp4.get_samples().get(self.sample_idx) {
+            Some(sample) => sample,
+            None => return Ok(None),
+        };
+        self.sample_idx += 1;
+        self.reader.seek(SeekFrom::Start(sample.pos))?;
+        let data = read_bytes(&mut self.reader, sample.size)?;
+        Ok(Some(TemporalUnit {
+            data,
+            pts: sample.time as i64 + sample.composition_offset as i64,
+            duration: Some(sample.duration as u64),
+            timescale: self.info.timescale,
+            is_sync: sample.is_sync,
+        }))
+    }
+
+    fn codec_config(&self) -> Option<&mp4::AV1CodecConfigurationBox> {
+        self.mp4.get_av1config().map(|(_, av1cc)| av1cc)
+    }
+
+    fn track_info(&self) -> &TrackInfo {
+        &self.info
+    }
+}
+
+///
+/// Low overhead bitstream format demuxer
+///
+/// Temporal units are delimited by OBU_TEMPORAL_DELIMITER.
+///
+pub struct ObuDemuxer<R> {
+    reader: R,
+    info: TrackInfo,
+    tu_count: i64,
+}
+
+impl<R: io::Read + io::Seek> ObuDemuxer<R> {
+    pub fn new(reader: R) -> Self {
+        ObuDemuxer {
+            reader,
+            info: TrackInfo {
+                format: FileFormat::Bitstream,
+                codec: String::new(),
+                size: None,
+                timescale: None,
+                length: None,
+            },
+            tu_count: 0,
+        }
+    }
+}
+
+impl<R: io::Read + io::Seek> Demuxer for ObuDemuxer<R> {
+    fn next_temporal_unit(&mut self) -> io::Result<Option<TemporalUnit>> {
+        let mut data = Vec::new();
+        loop {
+            let pos = self.reader.stream_position()?;
+            let obu = match obu::parse_obu_header(&mut self.reader, u32::MAX) {
+                Ok(obu) => obu,
+                Err(err) => {
+                    if self.reader.seek(SeekFrom::End(0))? == pos {
+                        // end of stream
+                        break;
+                    }
+                    if !data.is_empty() {
+                        // return the current temporal unit, report the error on the next call
+                        self.reader.seek(SeekFrom::Start(pos))?;
+                        break;
+                    }
+                    return Err(invalid_data(format!("invalid OBU at {}: {}", pos, err)));
+                }
+            };
+            self.reader.seek(SeekFrom::Start(pos))?;
+            if obu.obu_type == obu::OBU_TEMPORAL_DELIMITER && !data.is_empty() {
+                // beginning of the next temporal unit
+                break;
+            }
+            let obu_len = obu.header_len as u64 + obu.obu_size as u64;
+            match read_bytes(&mut self.reader, obu_len) {
+                Ok(obu_data) => data.extend_from_slice(&obu_data),
+                Err(_) => return Err(invalid_data(format!("truncated OBU at {}", pos))),
+            }
+        }
+        if data.is_empty() {
+            return Ok(None);
+        }
+        let pts = self.tu_count;
+        self.tu_count += 1;
+        Ok(Some(TemporalUnit {
+            is_sync: is_sync_sample(&data),
+            data,
+            pts,
+            duration: Some(1),
+            timescale: None,
+        }))
+    }
+
+    fn codec_config(&self) -> Option<&mp4::AV1CodecConfigurationBox> {
+        None
+    }
+
+    fn track_info(&self) -> &TrackInfo {
+        &self.info
+    }
+}
+
+///
+/// Annex B length delimited bitstream demuxer
+///
+pub struct AnnexBDemuxer<R> {
+    reader: R,
+    info: TrackInfo,
+    tu_count: i64,
+}
+
+impl<R: io::Read> AnnexBDemuxer<R> {
+    pub fn new(reader: R) -> Self {
+        AnnexBDemuxer {
+            reader,
+            info: TrackInfo {
+                format: FileFormat::AnnexB,
+                codec: String::new(),
+                size: None,
+                timescale: None,
+                length: None,
+            },
+            tu_count: 0,
+        }
+    }
+}
+
+impl<R: io::Read> Demuxer for AnnexBDemuxer<R> {
+    fn next_temporal_unit(&mut self) -> io::Result<Option<TemporalUnit>> {
+        // temporal_unit(temporal_unit_size)
+        let temporal_unit_size = match obu::leb128(&mut self.reader) {
+            Ok((_, temporal_unit_size)) => temporal_unit_size,
+            Err(_) => return Ok(None),
+        };
+        let temporal_unit = read_bytes(&mut self.reader, temporal_unit_size as u64)
+            .map_err(|_| invalid_data(format!("truncated temporal unit #{}", self.tu_count)))?;
+        let data = annexb::convert_temporal_unit(&temporal_unit)?;
+        let pts = self.tu_count;
+        self.tu_count += 1;
+        Ok(Some(TemporalUnit {
+            is_sync: is_sync_sample(&data),
+            data,
+            pts,
+            duration: Some(1),
+            timescale: None,
+        }))
+    }
+
+    fn codec_config(&self) -> Option<&mp4::AV1CodecConfigurationBox> {
+        None
+    }
+
+    fn track_info(&self) -> &TrackInfo {
+        &self.info
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    // OBU_TEMPORAL_DELIMITER, OBU_SEQUENCE_HEADER and OBU_FRAME_HEADER with the first payload byte
+    fn temporal_unit(sh: Option<u8>, fh: u8) -> Vec<u8> {
+        let mut data = vec![0x12, 0x00];
+        if let Some(sh) = sh {
+            data.extend_from_slice(&[0x0a, 0x01, sh]);
+        }
+        data.extend_from_slice(&[0x1a, 0x01, fh]);
+        data
+    }
+
+    #[test]
+    fn sync_sample() {
+        // shown key frame
+        assert!(is_sync_sample(&temporal_unit(Some(0x00), 0x10)));
+        // inter frame, hidden key frame, show_existing_frame
+        assert!(!is_sync_sample(&temporal_unit(Some(0x00), 0x30)));
+        assert!(!is_sync_sample(&temporal_unit(Some(0x00), 0x00)));
+        assert!(!is_sync_sample(&temporal_unit(Some(0x00), 0x90)));
+        // reduced_still_picture_header=1
+        assert!(is_sync_sample(&temporal_unit(Some(0x08), 0x00)));
+        // no sequence header
+        assert!(!is_sync_sample(&temporal_unit(None, 0x10)));
+    }
+
+    fn mp4_box(boxtype: &[u8; 4], payload: &[u8]) -> Vec<u8> {
+        let mut data = (8 + payload.len() as u32).to_be_bytes().to_vec();
+        data.extend_from_slice(boxtype);
+        data.extend_from_slice(payload);
+        data
+    }
+
+    // FullBox payload with u32 fields
+    fn full_box(boxtype: &[u8; 4], version: u8, fields: &[u32]) -> Vec<u8> {
+        let mut payload = vec![version, 0, 0, 0];
+        for field in fields {
+            payload.extend_from_slice(&field.to_be_bytes());
+        }
+        mp4_box(boxtype, &payload)
+    }
+
+    // ftyp, mdat and moov with one 'av01' track
+    fn mp4_file(samples: &[&[u8]], timescale: u32) -> Vec<u8> {
+        let mut file = mp4_box(b"ftyp", b"isom\0\0\0\0av01");
+        let chunk_offset = file.len() as u32 + 8;
+        file.extend(mp4_box(b"mdat", &samples.concat()));
+
+        // AV1SampleEntry(320x240) and AV1CodecConfigurationBox
+        let mut av01 = vec![0; 78];
+        av01[24..28].copy_from_slice(&[0x01, 0x40, 0x00, 0xf0]);
+        av01.extend(mp4_box(b"av1C", &[0x81, 0x00, 0x0c, 0x00]));
+        let mut stsd = vec![0, 0, 0, 0, 0, 0, 0, 1];
+        stsd.extend(mp4_box(b"av01", &av01));
+        let mut stsz = vec![0, samples.len() as u32];
+        stsz.extend(samples.iter().map(|s| s.len() as u32));
+        let stbl = [
+            mp4_box(b"stsd", &stsd),
+            full_box(b"stts", 0, &[1, samples.len() as u32, 3000]),
+            // composition offsets: 6000, 0
+            full_box(b"ctts", 0, &[2, 1, 6000, 1, 0]),
+            full_box(b"stss", 0, &[1, 1]),
+            full_box(b"stsc", 0, &[1, 1, samples.len() as u32, 1]),
+            full_box(b"stsz", 0, &stsz),
+            full_box(b"stco", 0, &[1, chunk_offset]),
+        ]
+        .concat();
+        // creation_time, modification_time, timescale, duration, language and pre_defined
+        let mdhd = full_box(b"mdhd", 0, &[0, 0, timescale, 0, 0]);
+        let minf = mp4_box(b"minf", &mp4_box(b"stbl", &stbl));
+        let mdia = mp4_box(b"mdia", &[mdhd, minf].concat());
+        file.extend(mp4_box(b"moov", &mp4_box(b"trak", &mdia)));
+        file
+    }
+
+    #[test]
+    fn mp4_demuxer() {
+        let (tu0, tu1) = (temporal_unit(Some(0x00), 0x10), temporal_unit(None, 0x30));
+        let file = mp4_file(&[&tu0, &tu1], 90000);
+        let mut demuxer = Mp4Demuxer::new(Cursor::new(file)).unwrap();
+        let info = demuxer.track_info();
+        assert_eq!(info.size, Some((320, 240)));
+        assert_eq!(info.timescale, Some((90000, 1)));
+        assert_eq!(info.length, Some(2));
+        assert_eq!(demuxer.codec_config().unwrap().seq_level_idx_0, 0);
+
+        // pts = decoding time + composition offset
+        let tu = demuxer.next_temporal_unit().unwrap().unwrap();
+        assert_eq!((tu.pts, tu.duration, tu.is_sync), (6000, Some(3000), true));
+        assert_eq!(tu.data, tu0);
+        let tu = demuxer.next_temporal_unit().unwrap().unwrap();
+        assert_eq!((tu.pts, tu.duration, tu.is_sync), (3000, Some(3000), false));
+        assert_eq!(tu.data, tu1);
+        assert!(demuxer.next_temporal_unit().unwrap().is_none());
+    }
+
+    // EBML element with 1 or 2 bytes data size
+    fn element(id: &[u8], payload: &[u8]) -> Vec<u8> {
+        let mut data = id.to_vec();
+        if payload.len() < 0x7f {
+            data.push(0x80 | payload.len() as u8);
+        } else {
+            data.extend_from_slice(&(0x4000 | payload.len() as u16).to_be_bytes());
+        }
+        data.extend_from_slice(payload);
+        data
+    }
+
+    fn simple_block(track_num: u8, timecode: i16, flags: u8, data: &[u8]) -> Vec<u8> {
+        let mut payload = vec![0x80 | track_num];
+        payload.extend_from_slice(&timecode.to_be_bytes());
+        payload.push(flags);
+        payload.extend_from_slice(data);
+        element(&[0xa3], &payload)
+    }
+
+    fn cluster(timecode: u8, blocks: &[Vec<u8>]) -> Vec<u8> {
+        let mut payload = element(&[0xe7], &[timecode]);
+        payload.extend(blocks.concat());
+        element(&[0x1f, 0x43, 0xb6, 0x75], &payload)
+    }
+
+    // EBML header and Segment with one AV1 track (DefaultDuration=40ms)
+    fn webm_file(timecode_scale: u32, clusters: &[Vec<u8>]) -> Vec<u8> {
+        let info = element(
+            &[0x15, 0x49, 0xa9, 0x66],
+            &element(&[0x2a, 0xd7, 0xb1], &timecode_scale.to_be_bytes()),
+        );
+        let track_entry = [
+            element(&[0xd7], &[1]),
+            element(&[0x86], b"V_AV1"),
+            element(&[0x23, 0xe3, 0x83], &40_000_000u32.to_be_bytes()),
+        ]
+        .concat();
+        let tracks = element(&[0x16, 0x54, 0xae, 0x6b], &element(&[0xae], &track_entry));
+        let segment = [info, tracks, clusters.concat()].concat();
+        let mut file = element(&[0x1a, 0x45, 0xdf, 0xa3], &[]);
+        file.extend(element(&[0x18, 0x53, 0x80, 0x67], &segment));
+        file
+    }
+
+    #[test]
+    fn mkv_demuxer() {
+        let (tu0, tu1) = (temporal_unit(Some(0x00), 0x10), temporal_unit(None, 0x30));
+        let clusters = [
+            cluster(
+                100,
+                &[
+                    simple_block(1, 0, mkv::BLOCK_FLAG_KEYFRAME, &tu0),
+                    simple_block(2, 0, 0, &[0xff; 4]), // non AV1 track
+                ],
+            ),
+            cluster(104, &[simple_block(1, -2, 0, &tu1)]),
+        ];
+        let file = webm_file(10_000_000, &clusters);
+        let mut demuxer = MkvDemuxer::new(Cursor::new(file)).unwrap();
+        assert_eq!(
+            demuxer.track_info().timescale,
+            Some((1_000_000_000, 10_000_000))
+        );
+
+        // DefaultDuration in TimecodeScale units
+        let tu = demuxer.next_temporal_unit().unwrap().unwrap();
+        assert_eq!((tu.pts, tu.duration, tu.is_sync), (100, Some(4), true));
+        assert_eq!(tu.data, tu0);
+        let tu = demuxer.next_temporal_unit().unwrap().unwrap();
+        assert_eq!((tu.pts, tu.duration, tu.is_sync), (102, Some(4), false));
+        assert_eq!(tu.data, tu1);
+        assert!(demuxer.next_temporal_unit().unwrap().is_none());
+
+        // TimecodeScale=0
+        let file = webm_file(0, &clusters);
+        let err = MkvDemuxer::new(Cursor::new(file)).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn mkv_demuxer_broken_block() {
+        let tu0 = temporal_unit(Some(0x00), 0x10);
+        let mut broken = cluster(100, &[simple_block(1, 0, mkv::BLOCK_FLAG_KEYFRAME, &tu0)]);
+        // invalid Element ID after the first SimpleBlock
+        broken[4] += 2;
+        broken.extend_from_slice(&[0x00, 0x00]);
+        let file = webm_file(1_000_000, &[broken]);
+        let mut demuxer = MkvDemuxer::new(Cursor::new(file)).unwrap();
+        assert_eq!(demuxer.next_temporal_unit().unwrap().unwrap().data, tu0);
+        let err = demuxer.next_temporal_unit().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn obu_demuxer() {
+        let (tu0, tu1) = (temporal_unit(Some(0x00), 0x10), temporal_unit(None, 0x30));
+        let mut demuxer = ObuDemuxer::new(Cursor::new([tu0.clone(), tu1.clone()].concat()));
+        let tu = demuxer.next_temporal_unit().unwrap().unwrap();
+        assert_eq!((tu.pts, tu.is_sync), (0, true));
+        assert_eq!(tu.data, tu0);
+        let tu = demuxer.next_temporal_unit().unwrap().unwrap();
+        assert_eq!((tu.pts, tu.is_sync), (1, false));
+        assert_eq!(tu.data, tu1);
+        assert!(demuxer.next_temporal_unit().unwrap().is_none());
+
+        // truncated OBU payload
+        let data = [&tu0[..], &[0x12, 0x00, 0x7a, 0x05, 0xaa]].concat();
+        let mut demuxer = ObuDemuxer::new(Cursor::new(data));
+        assert!(demuxer.next_temporal_unit().unwrap().is_some());
+        let err = demuxer.next_temporal_unit().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        // truncated OBU header
+        let data = [&tu0[..], &[0x12]].concat();
+        let mut demuxer = ObuDemuxer::new(Cursor::new(data));
+        assert!(demuxer.next_temporal_unit().unwrap().is_some());
+        let err = demuxer.next_temporal_unit().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn annexb_demuxer() {
+        // temporal_unit(): frame_unit(OBU_TEMPORAL_DELIMITER), frame_unit(OBU_PADDING)
+        let tu = [0x08, 0x02, 0x01, 0x10, 0x04, 0x03, 0x78, 0xaa, 0xbb];
+        let mut demuxer = AnnexBDemuxer::new(Cursor::new([&tu[..], &tu[..]].concat()));
+        for pts in 0..2 {
+            let tu = demuxer.next_temporal_unit().unwrap().unwrap();
+            assert_eq!(tu.pts, pts);
+            assert_eq!(tu.data, [0x12, 0x00, 0x7a, 0x02, 0xaa, 0xbb]);
+        }
+        assert!(demuxer.next_temporal_unit().unwrap().is_none());
+
+        // truncated temporal unit
+        let mut demuxer = AnnexBDemuxer::new(Cursor::new([&tu[..], &tu[..5]].concat()));
+        assert!(demuxer.next_temporal_unit().unwrap().is_some());
+        let err = demuxer.next_temporal_unit().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
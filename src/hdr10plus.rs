@@ -0,0 +1,392 @@
+//
+// HDR10+ dynamic metadata (SMPTE ST 2094-40) in ITU-T T.35 metadata OBU
+//
+// https://aomediacodec.github.io/av1-hdr10plus/
+//
+use crate::bitio::BitReader;
+use crate::obu;
+use std::fmt::Write;
+
+pub const ITU_T_T35_COUNTRY_CODE_USA: u8 = 0xB5;
+pub const ITU_T_T35_PROVIDER_CODE_SAMSUNG: u16 = 0x003C;
+pub const ITU_T_T35_PROVIDER_ORIENTED_CODE_HDR10PLUS: u16 = 0x0001;
+pub const APPLICATION_IDENTIFIER: u8 = 4;
+pub const APPLICATION_VERSION: u8 = 1;
+
+///
+/// HDR10+ dynamic metadata
+///
+#[derive(Debug, Default, PartialEq)]
+pub struct Hdr10PlusMetadata {
+    pub itu_t_t35_terminal_provider_code: u16,          // f(16)
+    pub itu_t_t35_terminal_provider_oriented_code: u16, // f(16)
+    pub application_identifier: u8,                     // f(8)
+    pub application_version: u8,                        // f(8)
+    pub num_windows: u8,                                // f(2)
+    pub processing_windows: Vec<ProcessingWindow>,      // window 1..num_windows-1
+    pub targeted_system_display_maximum_luminance: u32, // f(27)
+    pub targeted_system_display_actual_peak_luminance_flag: bool, // f(1)
+    pub targeted_system_display_actual_peak_luminance: Vec<Vec<u8>>, // f(4)
+    pub luminance_params: Vec<LuminanceParams>,         // window 0..num_windows-1
+    pub mastering_display_actual_peak_luminance_flag: bool, // f(1)
+    pub mastering_display_actual_peak_luminance: Vec<Vec<u8>>, // f(4)
+    pub tone_mapping_params: Vec<ToneMappingParams>,    // window 0..num_windows-1
+}
+
+///
+/// processing window
+///
+#[derive(Debug, Default, PartialEq)]
+pub struct ProcessingWindow {
+    pub window_upper_left_corner_x: u16,      // f(16)
+    pub window_upper_left_corner_y: u16,      // f(16)
+    pub window_lower_right_corner_x: u16,     // f(16)
+    pub window_lower_right_corner_y: u16,     // f(16)
+    pub center_of_ellipse_x: u16,             // f(16)
+    pub center_of_ellipse_y: u16,             // f(16)
+    pub rotation_angle: u8,                   // f(8)
+    pub semimajor_axis_internal_ellipse: u16, // f(16)
+    pub semimajor_axis_external_ellipse: u16, // f(16)
+    pub semiminor_axis_external_ellipse: u16, // f(16)
+    pub overlap_process_option: bool,         // f(1)
+}
+
+///
+/// scene luminance parameters of a window
+///
+#[derive(Debug, Default, PartialEq)]
+pub struct LuminanceParams {
+    pub maxscl: [u32; 3],                          // f(17)
+    pub average_maxrgb: u32,                       // f(17)
+    pub num_distribution_maxrgb_percentiles: u8,   // f(4)
+    pub distribution_maxrgb_percentages: Vec<u8>,  // f(7)
+    pub distribution_maxrgb_percentiles: Vec<u32>, // f(17)
+    pub fraction_bright_pixels: u16,               // f(10)
+}
+
+///
+/// tone mapping parameters of a window
+///
+#[derive(Debug, Default, PartialEq)]
+pub struct ToneMappingParams {
+    pub tone_mapping_flag: bool,             // f(1)
+    pub knee_point_x: u16,                   // f(12)
+    pub knee_point_y: u16,                   // f(12)
+    pub num_bezier_curve_anchors: u8,        // f(4)
+    pub bezier_curve_anchors: Vec<u16>,      // f(10)
+    pub color_saturation_mapping_flag: bool, // f(1)
+    pub color_saturation_weight: u8,         // f(6)
+}
+
+/// return true if T.35 metadata carries HDR10+ payload
+pub fn is_hdr10plus(t35: &obu::ItutT35Metadata) -> bool {
+    let p = &t35.itu_t_t35_payload_bytes;
+    t35.itu_t_t35_country_code == ITU_T_T35_COUNTRY_CODE_USA
+        && p.len() >= 5
+        && ((p[0] as u16) << 8 | p[1] as u16) == ITU_T_T35_PROVIDER_CODE_SAMSUNG
+        && ((p[2] as u16) << 8 | p[3] as u16) == ITU_T_T35_PROVIDER_ORIENTED_CODE_HDR10PLUS
+        && p[4] == APPLICATION_IDENTIFIER
+}
+
+///
+/// parse HDR10+ dynamic metadata from ITU-T T.35 metadata
+///
+pub fn parse_hdr10plus_metadata(t35: &obu::ItutT35Metadata) -> Option<Hdr10PlusMetadata> {
+    if !is_hdr10plus(t35) {
+        return None;
+    }
+    let mut br = BitReader::new(&t35.itu_t_t35_payload_bytes[..]);
+    let mut meta = Hdr10PlusMetadata {
+        itu_t_t35_terminal_provider_code: br.f::<u16>(16).ok()?, // f(16)
+        itu_t_t35_terminal_provider_oriented_code: br.f::<u16>(16).ok()?, // f(16)
+        application_identifier: br.f::<u8>(8).ok()?,             // f(8)
+        application_version: br.f::<u8>(8).ok()?,                // f(8)
+        ..Default::default()
+    };
+    if meta.application_version > APPLICATION_VERSION {
+        return None;
+    }
+    meta.num_windows = br.f::<u8>(2).ok()?; // f(2)
+    for _ in 1..meta.num_windows {
+        let pw = ProcessingWindow {
+            window_upper_left_corner_x: br.f::<u16>(16).ok()?, // f(16)
+            window_upper_left_corner_y: br.f::<u16>(16).ok()?, // f(16)
+            window_lower_right_corner_x: br.f::<u16>(16).ok()?, // f(16)
+            window_lower_right_corner_y: br.f::<u16>(16).ok()?, // f(16)
+            center_of_ellipse_x: br.f::<u16>(16).ok()?,        // f(16)
+            center_of_ellipse_y: br.f::<u16>(16).ok()?,        // f(16)
+            rotation_angle: br.f::<u8>(8).ok()?,               // f(8)
+            semimajor_axis_internal_ellipse: br.f::<u16>(16).ok()?, // f(16)
+            semimajor_axis_external_ellipse: br.f::<u16>(16).ok()?, // f(16)
+            semiminor_axis_external_ellipse: br.f::<u16>(16).ok()?, // f(16)
+            overlap_process_option: br.f::<bool>(1).ok()?,     // f(1)
+        };
+        meta.processing_windows.push(pw);
+    }
+    meta.targeted_system_display_maximum_luminance = br.f::<u32>(27).ok()?; // f(27)
+    meta.targeted_system_display_actual_peak_luminance_flag = br.f::<bool>(1).ok()?; // f(1)
+    if meta.targeted_system_display_actual_peak_luminance_flag {
+        meta.targeted_system_display_actual_peak_luminance = parse_peak_luminance(&mut br)?;
+    }
+    for _ in 0..meta.num_windows {
+        let mut lp = LuminanceParams::default();
+        for i in 0..3 {
+            lp.maxscl[i] = br.f::<u32>(17).ok()?; // f(17)
+        }
+        lp.average_maxrgb = br.f::<u32>(17).ok()?; // f(17)
+        lp.num_distribution_maxrgb_percentiles = br.f::<u8>(4).ok()?; // f(4)
+        for _ in 0..lp.num_distribution_maxrgb_percentiles {
+            lp.distribution_maxrgb_percentages.push(br.f::<u8>(7).ok()?); // f(7)
+            lp.distribution_maxrgb_percentiles
+                .push(br.f::<u32>(17).ok()?); // f(17)
+        }
+        lp.fraction_bright_pixels = br.f::<u16>(10).ok()?; // f(10)
+        meta.luminance_params.push(lp);
+    }
+    meta.mastering_display_actual_peak_luminance_flag = br.f::<bool>(1).ok()?; // f(1)
+    if meta.mastering_display_actual_peak_luminance_flag {
+        meta.mastering_display_actual_peak_luminance = parse_peak_luminance(&mut br)?;
+    }
+    for _ in 0..meta.num_windows {
+        let mut tmp = ToneMappingParams {
+            tone_mapping_flag: br.f::<bool>(1).ok()?, // f(1)
+            ..Default::default()
+        };
+        if tmp.tone_mapping_flag {
+            tmp.knee_point_x = br.f::<u16>(12).ok()?; // f(12)
+            tmp.knee_point_y = br.f::<u16>(12).ok()?; // f(12)
+            tmp.num_bezier_curve_anchors = br.f::<u8>(4).ok()?; // f(4)
+            for _ in 0..tmp.num_bezier_curve_anchors {
+                tmp.bezier_curve_anchors.push(br.f::<u16>(10).ok()?); // f(10)
+            }
+        }
+        tmp.color_saturation_mapping_flag = br.f::<bool>(1).ok()?; // f(1)
+        if tmp.color_saturation_mapping_flag {
+            tmp.color_saturation_weight = br.f::<u8>(6).ok()?; // f(6)
+        }
+        meta.tone_mapping_params.push(tmp);
+    }
+
+    Some(meta)
+}
+
+/// parse actual peak luminance matrix
+fn parse_peak_luminance(br: &mut BitReader) -> Option<Vec<Vec<u8>>> {
+    let num_rows = br.f::<usize>(5).ok()?; // f(5)
+    let num_cols = br.f::<usize>(5).ok()?; // f(5)
+    let mut peak_luminance = Vec::with_capacity(num_rows);
+    for _ in 0..num_rows {
+        let mut row = Vec::with_capacity(num_cols);
+        for _ in 0..num_cols {
+            row.push(br.f::<u8>(4).ok()?); // f(4)
+        }
+        peak_luminance.push(row);
+    }
+    Some(peak_luminance)
+}
+
+/// format values as JSON array
+fn json_array<T: ToString>(values: &[T]) -> String {
+    let values: Vec<String> = values.iter().map(|v| v.to_string()).collect();
+    format!("[{}]", values.join(", "))
+}
+
+///
+/// export HDR10+ metadata in JSON layout of HDR10+ tools
+///
+/// `frames` is a list of (frame index in presentation order, metadata).
+/// Consecutive frames with identical metadata are grouped into one scene.
+///
+pub fn export_json(frames: &[(i64, Hdr10PlusMetadata)]) -> String {
+    let mut scene_first_frame_index = Vec::new();
+    let mut scene_frame_numbers: Vec<u32> = Vec::new();
+    let mut scene_info = Vec::new();
+    let mut tone_mapping = false;
+    let mut scene_frame_index = 0;
+    for (i, &(frame_index, ref meta)) in frames.iter().enumerate() {
+        if i == 0 || frames[i - 1].1 != *meta {
+            scene_first_frame_index.push(frame_index);
+            scene_frame_numbers.push(0);
+            scene_frame_index = 0;
+        }
+        *scene_frame_numbers.last_mut().unwrap() += 1;
+
+        // HDR10+ tools export the first processing window
+        let mut s = String::new();
+        s.push_str("    {\n");
+        if let Some(tmp) = meta.tone_mapping_params.first() {
+            if tmp.tone_mapping_flag {
+                tone_mapping = true;
+                s.push_str("      \"BezierCurveData\": {\n");
+                let _ = writeln!(
+                    s,
+                    "        \"Anchors\": {},",
+                    json_array(&tmp.bezier_curve_anchors)
+                );
+                let _ = writeln!(s, "        \"KneePointX\": {},", tmp.knee_point_x);
+                let _ = writeln!(s, "        \"KneePointY\": {}", tmp.knee_point_y);
+                s.push_str("      },\n");
+            }
+        }
+        if let Some(lp) = meta.luminance_params.first() {
+            s.push_str("      \"LuminanceParameters\": {\n");
+            let _ = writeln!(s, "        \"AverageRGB\": {},", lp.average_maxrgb);
+            s.push_str("        \"LuminanceDistributions\": {\n");
+            let _ = writeln!(
+                s,
+                "          \"DistributionIndex\": {},",
+                json_array(&lp.distribution_maxrgb_percentages)
+            );
+            let _ = writeln!(
+                s,
+                "          \"DistributionValues\": {}",
+                json_array(&lp.distribution_maxrgb_percentiles)
+            );
+            s.push_str("        },\n");
+            let _ = writeln!(s, "        \"MaxScl\": {}", json_array(&lp.maxscl));
+            s.push_str("      },\n");
+        }
+        let _ = writeln!(s, "      \"NumberOfWindows\": {},", meta.num_windows);
+        let _ = writeln!(
+            s,
+            "      \"TargetedSystemDisplayMaximumLuminance\": {},",
+            meta.targeted_system_display_maximum_luminance
+        );
+        let _ = writeln!(s, "      \"SceneFrameIndex\": {},", scene_frame_index);
+        let _ = writeln!(
+            s,
+            "      \"SceneId\": {},",
+            scene_first_frame_index.len() - 1
+        );
+        let _ = writeln!(s, "      \"SequenceFrameIndex\": {}", frame_index);
+        s.push_str("    }");
+        scene_info.push(s);
+        scene_frame_index += 1;
+    }
+
+    let mut json = String::new();
+    json.push_str("{\n");
+    json.push_str("  \"JSONInfo\": {\n");
+    let _ = writeln!(
+        json,
+        "    \"HDR10plusProfile\": \"{}\",",
+        if tone_mapping { "B" } else { "A" }
+    );
+    json.push_str("    \"Version\": \"1.0\"\n");
+    json.push_str("  },\n");
+    json.push_str("  \"SceneInfo\": [\n");
+    if !scene_info.is_empty() {
+        json.push_str(&scene_info.join(",\n"));
+        json.push('\n');
+    }
+    json.push_str("  ],\n");
+    json.push_str("  \"SceneInfoSummary\": {\n");
+    let _ = writeln!(
+        json,
+        "    \"SceneFirstFrameIndex\": {},",
+        json_array(&scene_first_frame_index)
+    );
+    let _ = writeln!(
+        json,
+        "    \"SceneFrameNumbers\": {}",
+        json_array(&scene_frame_numbers)
+    );
+    json.push_str("  },\n");
+    json.push_str("  \"ToolInfo\": {\n");
+    let _ = writeln!(json, "    \"Tool\": \"{}\",", env!("CARGO_PKG_NAME"));
+    let _ = writeln!(json, "    \"Version\": \"{}\"", env!("CARGO_PKG_VERSION"));
+    json.push_str("  }\n");
+    json.push_str("}\n");
+    json
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bitio::pack_bits;
+
+    // user_data_registered_itu_t_t35() with one window and tone mapping
+    fn t35_payload(application_version: u64) -> Vec<u8> {
+        pack_bits(&[
+            (0x003c, 16),             // itu_t_t35_terminal_provider_code
+            (0x0001, 16),             // itu_t_t35_terminal_provider_oriented_code
+            (4, 8),                   // application_identifier
+            (application_version, 8), // application_version
+            (1, 2),                   // num_windows
+            (400, 27),                // targeted_system_display_maximum_luminance
+            (0, 1),                   // targeted_system_display_actual_peak_luminance_flag
+            (1000, 17),               // maxscl[0]
+            (2000, 17),               // maxscl[1]
+            (3000, 17),               // maxscl[2]
+            (500, 17),                // average_maxrgb
+            (2, 4),                   // num_distribution_maxrgb_percentiles
+            (1, 7),                   // distribution_maxrgb_percentages[0]
+            (100, 17),                // distribution_maxrgb_percentiles[0]
+            (99, 7),                  // distribution_maxrgb_percentages[1]
+            (900, 17),                // distribution_maxrgb_percentiles[1]
+            (0, 10),                  // fraction_bright_pixels
+            (0, 1),                   // mastering_display_actual_peak_luminance_flag
+            (1, 1),                   // tone_mapping_flag
+            (10, 12),                 // knee_point_x
+            (20, 12),                 // knee_point_y
+            (2, 4),                   // num_bezier_curve_anchors
+            (300, 10),                // bezier_curve_anchors[0]
+            (600, 10),                // bezier_curve_anchors[1]
+            (0, 1),                   // color_saturation_mapping_flag
+        ])
+    }
+
+    fn t35(payload: Vec<u8>) -> obu::ItutT35Metadata {
+        obu::ItutT35Metadata {
+            itu_t_t35_country_code: ITU_T_T35_COUNTRY_CODE_USA,
+            itu_t_t35_country_code_extension_byte: None,
+            itu_t_t35_payload_bytes: payload,
+        }
+    }
+
+    #[test]
+    fn parse_metadata() {
+        let meta = parse_hdr10plus_metadata(&t35(t35_payload(1))).unwrap();
+        assert_eq!(meta.application_version, 1);
+        assert_eq!(meta.num_windows, 1);
+        assert!(meta.processing_windows.is_empty());
+        assert_eq!(meta.targeted_system_display_maximum_luminance, 400);
+        assert_eq!(
+            meta.luminance_params,
+            [LuminanceParams {
+                maxscl: [1000, 2000, 3000],
+                average_maxrgb: 500,
+                num_distribution_maxrgb_percentiles: 2,
+                distribution_maxrgb_percentages: vec![1, 99],
+                distribution_maxrgb_percentiles: vec![100, 900],
+                fraction_bright_pixels: 0,
+            }]
+        );
+        assert!(!meta.mastering_display_actual_peak_luminance_flag);
+        assert_eq!(
+            meta.tone_mapping_params,
+            [ToneMappingParams {
+                tone_mapping_flag: true,
+                knee_point_x: 10,
+                knee_point_y: 20,
+                num_bezier_curve_anchors: 2,
+                bezier_curve_anchors: vec![300, 600],
+                ..Default::default()
+            }]
+        );
+    }
+
+    #[test]
+    fn reject_metadata() {
+        // unknown application_version
+        assert!(parse_hdr10plus_metadata(&t35(t35_payload(2))).is_none());
+        // truncated payload
+        let mut payload = t35_payload(1);
+        payload.truncate(20);
+        assert!(parse_hdr10plus_metadata(&t35(payload)).is_none());
+        // other country code
+        let mut t35 = t35(t35_payload(1));
+        t35.itu_t_t35_country_code = 0x26;
+        assert!(!is_hdr10plus(&t35));
+    }
+}
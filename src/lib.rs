@@ -1,23 +1,46 @@
 extern crate byteorder;
 extern crate hex;
 
+pub mod annexb;
 pub mod av1;
 mod bitio;
+pub mod captions;
+pub mod demux;
+pub mod hdr10plus;
 pub mod ivf;
 pub mod mkv;
 pub mod mp4;
 pub mod obu;
+pub mod timecode;
 
+use std::fmt;
 use std::io;
+use std::io::Read;
 
 pub const FCC_AV01: [u8; 4] = *b"AV01"; // AV1 codec
 const WEBM_SIGNATURE: [u8; 4] = [0x1A, 0x45, 0xDF, 0xA3]; // EBML(Matroska/WebM)
+const ANNEXB_PROBE_SIZE: u64 = 65536; // [byte]
 
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum FileFormat {
     IVF,       // IVF format
     WebM,      // Matroska/WebM format
     MP4,       // ISOBMFF/MP4 format
     Bitstream, // Raw bitstream
+    AnnexB,    // Length delimited bitstream
+}
+
+impl fmt::Display for FileFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match *self {
+            FileFormat::IVF => "IVF",
+            FileFormat::WebM => "Matroska/WebM",
+            FileFormat::MP4 => "ISOBMFF/MP4",
+            FileFormat::Bitstream => "Raw stream",
+            FileFormat::AnnexB => "Annex B",
+        };
+        write!(f, "{}", name)
+    }
 }
 
 /// probe file format
@@ -28,10 +51,19 @@ pub fn probe_fileformat<R: io::Read>(reader: &mut R) -> io::Result<FileFormat> {
         ivf::IVF_SIGNATURE => FileFormat::IVF,
         WEBM_SIGNATURE => FileFormat::WebM,
         _ => {
+            let mut buf = b4.to_vec();
             reader.read_exact(&mut b4)?;
+            buf.extend_from_slice(&b4);
             match b4 {
                 mp4::BOX_FILETYPE => FileFormat::MP4,
-                _ => FileFormat::Bitstream,
+                _ => {
+                    reader.take(ANNEXB_PROBE_SIZE).read_to_end(&mut buf)?;
+                    if annexb::probe(&buf) {
+                        FileFormat::AnnexB
+                    } else {
+                        FileFormat::Bitstream
+                    }
+                }
             }
         }
     };
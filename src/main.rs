@@ -1,366 +1,314 @@
 extern crate av1parser;
-extern crate byteorder;
 #[macro_use]
 extern crate clap;
-extern crate hex;
 
 use av1parser::*;
 use clap::{App, Arg};
-use std::cmp;
 use std::fs;
 use std::io;
-use std::io::{Seek, SeekFrom};
-
-mod av1;
-mod bitio;
-mod ivf;
-mod mkv;
-mod mp4;
-mod obu;
 
 /// application global config
 struct AppConfig {
     verbose: u64,
+    operating_point: usize,
+    hdr10plus: Option<String>,
+    srt: Option<String>,
+    scc: Option<String>,
+}
+
+/// metadata collected for export
+#[derive(Default)]
+struct Exports {
+    hdr10plus: Vec<(i64, hdr10plus::Hdr10PlusMetadata)>, // HDR10+ metadata with present order
+    cc_data: Vec<(i64, captions::CcData)>,               // A/53 closed captions with present order
 }
 
 ///
 /// process OBU(Open Bitstream Unit)
 ///
-fn process_obu<R: io::Read>(
-    reader: &mut R,
-    seq: &mut av1::Sequence,
+fn process_obu(
+    parser: &mut av1::StreamParser,
     obu: &obu::Obu,
+    payload: &[u8],
     config: &AppConfig,
+    exports: &mut Exports,
 ) {
-    let reader = &mut io::Read::take(reader, obu.obu_size as u64);
-    match obu.obu_type {
-        obu::OBU_SEQUENCE_HEADER => {
-            if let Some(sh) = obu::parse_sequence_header(reader) {
-                if config.verbose > 1 {
-                    println!("  {:?}", sh);
+    for event in parser.parse_obu(obu, payload) {
+        let seq = &parser.seq;
+        match event {
+            av1::Event::Dropped => {
+                if config.verbose > 0 {
+                    println!(
+                        "    dropped (not in operating point {})",
+                        seq.operating_point
+                    );
                 }
-                seq.sh = Some(sh);
-            } else {
-                println!("  invalid SequenceHeader");
             }
-        }
-        obu::OBU_FRAME_HEADER | obu::OBU_FRAME => {
-            if seq.sh.is_none() {
+            av1::Event::SequenceHeader(sh) => {
                 if config.verbose > 1 {
-                    println!("  no sequence header");
+                    println!("  {:?}", sh);
                 }
-                return;
             }
-            if let Some(fh) =
-                obu::parse_frame_header(reader, seq.sh.as_ref().unwrap(), &mut seq.rfman)
-            {
-                if !fh.show_existing_frame {
-                    let error_resilient = if fh.error_resilient_mode { "*" } else { "" };
-                    if fh.show_frame {
-                        println!(
-                            "  #{} {}{}, update({}), show@{}",
-                            seq.rfman.decode_order,
-                            av1::stringify::frame_type(fh.frame_type),
-                            error_resilient,
-                            av1::stringify::ref_frame(fh.refresh_frame_flags),
-                            seq.rfman.present_order
-                        );
-                    } else {
-                        println!(
-                            "  #{} {}{}, update({}), {}",
-                            seq.rfman.decode_order,
-                            av1::stringify::frame_type(fh.frame_type),
-                            error_resilient,
-                            av1::stringify::ref_frame(fh.refresh_frame_flags),
-                            if fh.showable_frame {
-                                "showable"
-                            } else {
-                                "(refonly)"
-                            }
-                        );
-                    }
+            av1::Event::FrameHeader {
+                decode_order,
+                present_order,
+                header: fh,
+            } => {
+                let error_resilient = if fh.error_resilient_mode { "*" } else { "" };
+                if let Some(present_order) = present_order {
+                    println!(
+                        "  #{} {}{}, update({}), show@{}",
+                        decode_order,
+                        av1::stringify::frame_type(fh.frame_type),
+                        error_resilient,
+                        av1::stringify::ref_frame(fh.refresh_frame_flags),
+                        present_order
+                    );
                 } else {
-                    let show_idx = fh.frame_to_show_map_idx;
                     println!(
-                        "    #{} ({}) show@{}",
-                        seq.rfman.frame_buf[show_idx as usize],
-                        av1::stringify::ref_frame(1 << show_idx),
-                        seq.rfman.present_order,
+                        "  #{} {}{}, update({}), {}",
+                        decode_order,
+                        av1::stringify::frame_type(fh.frame_type),
+                        error_resilient,
+                        av1::stringify::ref_frame(fh.refresh_frame_flags),
+                        if fh.showable_frame {
+                            "showable"
+                        } else {
+                            "(refonly)"
+                        }
                     );
                 }
                 if config.verbose > 1 {
                     println!("  {:?}", fh);
                 }
-
-                // decode_frame_wrapup(): Decode frame wrapup process
-                if fh.show_frame || fh.show_existing_frame {
-                    seq.rfman.output_process(&fh);
+                if config.verbose > 2 {
+                    println!("  {:?}", seq.rfman);
                 }
-                if !fh.show_existing_frame {
-                    if config.verbose > 2 {
-                        println!("  {:?}", seq.rfman);
-                    }
-                    seq.rfman.update_process(&fh);
+            }
+            av1::Event::ShowExisting {
+                decode_order,
+                present_order,
+                header: fh,
+            } => {
+                println!(
+                    "    #{} ({}) show@{}",
+                    decode_order,
+                    av1::stringify::ref_frame(1 << fh.frame_to_show_map_idx),
+                    present_order,
+                );
+                if config.verbose > 1 {
+                    println!("  {:?}", fh);
+                }
+                if config.verbose > 2 && fh.frame_type == obu::KEY_FRAME {
+                    println!("  {:?}", seq.rfman);
                 }
             }
-        }
-        obu::OBU_TILE_LIST => {
-            if let Some(tl) = obu::parse_tile_list(reader) {
+            av1::Event::FrameHeaderCopy => {
+                if config.verbose > 1 {
+                    println!("  frame_header_copy()");
+                }
+            }
+            av1::Event::TileGroup(tg) => {
+                if config.verbose > 1 {
+                    println!("  {:?}", tg);
+                }
+            }
+            av1::Event::TileList(tl) => {
+                println!(
+                    "  output {}x{} tiles, {} entries",
+                    tl.output_frame_width_in_tiles_minus_1 as u32 + 1,
+                    tl.output_frame_height_in_tiles_minus_1 as u32 + 1,
+                    tl.tile_count_minus_1 as u32 + 1
+                );
+                if config.verbose > 1 {
+                    for tle in &tl.tile_list_entries {
+                        if let Some(anchor) = seq.anchor_frame(tle) {
+                            println!(
+                                "    anchor #{} tile({},{}) data={}+{}",
+                                anchor,
+                                tle.anchor_tile_row,
+                                tle.anchor_tile_col,
+                                tle.offset,
+                                tle.size
+                            );
+                        } else {
+                            println!("    invalid anchor_frame_idx={}", tle.anchor_frame_idx);
+                        }
+                    }
+                }
                 if config.verbose > 2 {
                     println!("  {:?}", tl);
                 }
-            } else {
-                println!("  invalid TileList")
             }
-        }
-        obu::OBU_METADATA => {
-            if let Ok(metadata) = obu::parse_metadata_obu(reader) {
+            av1::Event::Metadata(metadata) => {
                 if config.verbose > 1 {
                     println!("    {:?}", metadata);
                 }
-            } else {
-                println!("    invalid MetadataObu");
+                if let obu::MetadataObu::Scalability(ref sc) = metadata {
+                    if config.verbose > 0 {
+                        if let Some(mode) = sc.mode() {
+                            println!(
+                                "    scalability {}: {} spatial, {} temporal, ratio {}:{}, inter-layer {:?}",
+                                mode.name,
+                                mode.spatial_layers,
+                                mode.temporal_layers,
+                                mode.resolution_ratio.0,
+                                mode.resolution_ratio.1,
+                                mode.inter_layer_prediction
+                            );
+                        }
+                    }
+                }
             }
-        }
-        _ => {}
-    }
-}
-
-/// parse IVF format
-fn parse_ivf_format<R: io::Read + io::Seek>(
-    mut reader: R,
-    fname: &str,
-    config: &AppConfig,
-) -> io::Result<()> {
-    // parse IVF header
-    let mut ivf_header = [0; ivf::IVF_HEADER_SIZE];
-    reader.read_exact(&mut ivf_header)?;
-    match ivf::parse_ivf_header(&ivf_header) {
-        Ok(hdr) => {
-            let codec = String::from_utf8(hdr.codec.to_vec()).unwrap();
-            println!(
-                "{}: IVF codec={:?} size={}x{} timescale={}/{} length={}",
-                fname,
-                codec,
-                hdr.width,
-                hdr.height,
-                hdr.timescale_num,
-                hdr.timescale_den,
-                hdr.length
-            );
-            if hdr.codec != FCC_AV01 {
-                println!(
-                    "{}: unsupport codec(0x{})",
-                    fname,
-                    hex::encode_upper(hdr.codec)
-                );
-                return Ok(());
+            av1::Event::Hdr10Plus {
+                present_order,
+                metadata,
+            } => {
+                if config.verbose > 1 {
+                    println!("    {:?}", metadata);
+                }
+                exports.hdr10plus.push((present_order, metadata));
             }
-        }
-        Err(msg) => {
-            println!("{}: {}", fname, msg);
-            return Ok(());
-        }
-    };
-
-    let mut seq = av1::Sequence::new();
-
-    // parse IVF frames
-    while let Ok(frame) = ivf::parse_ivf_frame(&mut reader) {
-        if config.verbose > 0 {
-            println!("IVF F#{} size={}", frame.pts, frame.size);
-        }
-        let mut sz = frame.size;
-        let pos = reader.stream_position()?;
-        // parse OBU(open bitstream unit)s
-        while sz > 0 {
-            let obu = obu::parse_obu_header(&mut reader, sz)?;
-            if config.verbose > 0 {
-                println!("  {}", obu);
+            av1::Event::CcData {
+                present_order,
+                cc_data,
+            } => {
+                if config.verbose > 1 {
+                    println!("    {:?}", cc_data);
+                }
+                exports.cc_data.push((present_order, cc_data));
+            }
+            av1::Event::Timecode { timecode, .. } => {
+                if config.verbose > 0 {
+                    println!("    timecode {}", timecode);
+                }
             }
-            sz -= obu.header_len + obu.obu_size;
-            let pos = reader.stream_position()?;
-            process_obu(&mut reader, &mut seq, &obu, config);
-            reader.seek(SeekFrom::Start(pos + obu.obu_size as u64))?;
+            av1::Event::Warning(warning) => match warning {
+                av1::Warning::NoSequenceHeader | av1::Warning::NoFrameHeader => {
+                    if config.verbose > 1 {
+                        println!("  {}", warning);
+                    }
+                }
+                av1::Warning::Timecode(_)
+                | av1::Warning::Invalid {
+                    syntax: "MetadataObu",
+                    ..
+                } => println!("    {}", warning),
+                _ => println!("  {}", warning),
+            },
+            av1::Event::TemporalDelimiter | av1::Event::Padding(_) => {}
         }
-        reader.seek(SeekFrom::Start(pos + frame.size as u64))?;
     }
-    Ok(())
 }
 
-/// parse WebM format
-fn parse_webm_format<R: io::Read + io::Seek>(
-    mut reader: R,
-    fname: &str,
+/// process OBUs in byte buffer (IVF frame, WebM block, MP4 sample, etc.)
+fn process_obus(
+    data: &[u8],
+    parser: &mut av1::StreamParser,
     config: &AppConfig,
+    exports: &mut Exports,
 ) -> io::Result<()> {
-    // open Matroska/WebM file
-    let mut webm = mkv::open_mkvfile(&mut reader)?;
-
-    let codec_id = mkv::CODEC_V_AV1;
-    let track_num = match webm.find_track(codec_id) {
-        Some(num) => num,
-        _ => {
-            println!("{}: Matroska/WebM \"{}\" codec not found", fname, codec_id);
-            return Ok(());
-        }
-    };
-    match webm.get_videosetting(track_num) {
-        Some(video) => println!(
-            "{}: Matroska/WebM codec=\"{}\" size={}x{}",
-            fname, codec_id, video.pixel_width, video.pixel_height
-        ),
-        None => println!(
-            "{}: Matroska/WebM codec=\"{}\" size=(unknown)",
-            fname, codec_id
-        ),
-    }
-
-    let mut seq = av1::Sequence::new();
-
-    // parse WebM block
-    while let Ok(Some(block)) = webm.next_block(&mut reader) {
-        if block.track_num != track_num {
-            // skip non AV1 track data
-            continue;
-        }
-
+    for obu_ref in obu::ObuIter::new(data) {
+        let obu_ref = obu_ref.map_err(|err| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                err.with_obu_index(parser.seq.obu_count),
+            )
+        })?;
         if config.verbose > 0 {
-            println!(
-                "MKV F#{} flags=0x{:02x} size={}",
-                block.timecode, block.flags, block.size
-            );
-        }
-        let mut sz = block.size as u32;
-        // parse OBU(open bitstream unit)s
-        while sz > 0 {
-            let obu = obu::parse_obu_header(&mut reader, sz)?;
-            if config.verbose > 0 {
-                println!("  {}", obu);
-            }
-            sz -= obu.header_len + obu.obu_size;
-            let pos = reader.stream_position()?;
-            process_obu(&mut reader, &mut seq, &obu, config);
-            reader.seek(SeekFrom::Start(pos + obu.obu_size as u64))?;
+            println!("  {}", obu_ref.obu);
         }
-
-        reader.seek(SeekFrom::Start(block.offset + block.size))?;
+        process_obu(parser, &obu_ref.obu, obu_ref.payload, config, exports);
     }
     Ok(())
 }
 
-/// parse MP4(ISOBMFF) format
-fn parse_mp4_format<R: io::Read + io::Seek>(
-    mut reader: R,
-    fname: &str,
-    config: &AppConfig,
-) -> io::Result<()> {
-    // open MP4(ISOBMFF) file
-    let mp4 = mp4::open_mp4file(&mut reader)?;
-    if config.verbose > 1 {
-        println!("  {:?}", mp4.get_filetype());
+/// temporal unit label in verbose log
+fn unit_label(fmt: FileFormat) -> &'static str {
+    match fmt {
+        FileFormat::IVF => "IVF",
+        FileFormat::WebM => "MKV",
+        FileFormat::MP4 => "MP4",
+        FileFormat::Bitstream => "Raw",
+        FileFormat::AnnexB => "AnnexB",
     }
+}
 
-    let brand_av01 = mp4::FCC::from(mp4::BRAND_AV01);
-    let brands = &mp4.get_filetype().compatible_brands;
-    if !brands.iter().any(|b| *b == brand_av01) {
-        println!("{}: ISOBMFF/MP4 {} brand not found", fname, brand_av01);
-        return Ok(());
-    }
-    let (av1se, av1cc) = match mp4.get_av1config() {
-        Some(config) => config,
-        None => {
-            println!("{}: ISOBMFF/MP4 {} track not found", fname, brand_av01);
+/// process input file
+fn process_file(fname: &str, config: &AppConfig) -> io::Result<()> {
+    // open input file as read-only mode
+    let f = fs::OpenOptions::new().read(true).open(fname)?;
+    let reader = io::BufReader::new(f);
+
+    // probe media container format
+    let mut demuxer = match demux::open_any(reader) {
+        Ok(demuxer) => demuxer,
+        Err(ref err) if err.kind() == io::ErrorKind::InvalidData => {
+            println!("{}: {}", fname, err);
             return Ok(());
         }
+        Err(err) => return Err(err),
     };
-    println!(
-        "{}: ISOBMFF/MP4 codec={} size={}x{}",
-        fname, brand_av01, av1se.width, av1se.height
-    );
-    if config.verbose > 1 {
-        println!("  {:?}", av1se);
-        println!("  {:?}", av1cc);
+    let info = demuxer.track_info();
+    let label = unit_label(info.format);
+    print!("{}: {}", fname, info.format);
+    if !info.codec.is_empty() {
+        print!(" codec={:?}", info.codec);
+        match info.size {
+            Some((width, height)) => print!(" size={}x{}", width, height),
+            None => print!(" size=(unknown)"),
+        }
+    }
+    if let Some((num, den)) = info.timescale {
+        print!(" timescale={}/{}", num, den);
+    }
+    if let Some(length) = info.length {
+        print!(" length={}", length);
     }
+    println!();
 
-    let mut seq = av1::Sequence::new();
+    let mut parser = av1::StreamParser::new(config.operating_point);
+    let mut exports = Exports::default();
 
-    // process AV1CodecConfigurationBox::configOBUs
-    let mut cur = io::Cursor::new(av1cc.config_obus.clone());
-    let mut config_sz = av1cc.config_obus.len() as u32;
-    while config_sz > 0 {
-        let obu = obu::parse_obu_header(&mut cur, config_sz)?;
-        if config.verbose > 0 {
-            println!("  {}", obu);
+    // process AV1CodecConfigurationRecord::configOBUs
+    if let Some(av1cc) = demuxer.codec_config() {
+        if config.verbose > 1 {
+            println!("  {:?}", av1cc);
         }
-        config_sz -= obu.header_len + obu.obu_size;
-        process_obu(&mut cur, &mut seq, &obu, config);
+        process_obus(&av1cc.config_obus, &mut parser, config, &mut exports)?;
     }
 
-    // parse AV1 Samples
-    for sample in mp4.get_samples() {
-        reader.seek(SeekFrom::Start(sample.pos))?;
-        let mut sz = sample.size;
-        // parse OBU(open bitstream unit)s
-        while sz > 0 {
-            let obu_size = cmp::min(sz, u32::MAX as u64) as u32;
-            let obu = obu::parse_obu_header(&mut reader, obu_size)?;
-            if config.verbose > 0 {
-                println!("  {}", obu);
+    // parse temporal units
+    loop {
+        let tu = match demuxer.next_temporal_unit() {
+            Ok(Some(tu)) => tu,
+            Ok(None) => break,
+            Err(ref err) if err.kind() == io::ErrorKind::InvalidData => {
+                println!("{}: {}", fname, err);
+                break;
             }
-            sz -= (obu.header_len + obu.obu_size) as u64;
-            let pos = reader.stream_position()?;
-            process_obu(&mut reader, &mut seq, &obu, config);
-            reader.seek(SeekFrom::Start(pos + obu.obu_size as u64))?;
-        }
-    }
-    Ok(())
-}
-
-/// parse low overhead bitstream format
-fn parse_obu_bitstream<R: io::Read + io::Seek>(
-    mut reader: R,
-    fname: &str,
-    config: &AppConfig,
-) -> io::Result<()> {
-    println!("{}: Raw stream", fname);
-
-    let mut seq = av1::Sequence::new();
-    let sz = u32::MAX;
-    let mut fnum = 0;
-
-    // parse OBU(open bitstream unit)s sequence
-    while let Ok(obu) = obu::parse_obu_header(&mut reader, sz) {
+            Err(err) => return Err(err),
+        };
         if config.verbose > 0 {
-            if obu.obu_type == obu::OBU_TEMPORAL_DELIMITER {
-                println!("Raw F#{}", fnum);
-                fnum += 1;
-            }
-            println!("  {}", obu);
+            println!("{} F#{} size={}", label, tu.pts, tu.data.len());
         }
-        let pos = reader.stream_position()?;
-        process_obu(&mut reader, &mut seq, &obu, config);
-        reader.seek(SeekFrom::Start(pos + obu.obu_size as u64))?;
+        // parse OBU(open bitstream unit)s
+        process_obus(&tu.data, &mut parser, config, &mut exports)?;
     }
-    Ok(())
-}
 
-/// process input file
-fn process_file(fname: &str, config: &AppConfig) -> io::Result<()> {
-    // open input file as read-only mode
-    let f = fs::OpenOptions::new().read(true).open(fname)?;
-    let mut reader = io::BufReader::new(f);
-
-    // probe media container format
-    let fmt = probe_fileformat(&mut reader)?;
-    reader.seek(SeekFrom::Start(0))?;
-
-    match fmt {
-        FileFormat::IVF => parse_ivf_format(reader, fname, config)?,
-        FileFormat::WebM => parse_webm_format(reader, fname, config)?,
-        FileFormat::MP4 => parse_mp4_format(reader, fname, config)?,
-        FileFormat::Bitstream => parse_obu_bitstream(reader, fname, config)?,
-    };
+    for err in parser.layers.check_scalability() {
+        println!("{}: {}", fname, err);
+    }
+    let frame_rate = parser.seq.frame_rate();
+    if let Some(ref path) = config.hdr10plus {
+        fs::write(path, hdr10plus::export_json(&exports.hdr10plus))?;
+    }
+    if let Some(ref path) = config.srt {
+        fs::write(path, captions::export_srt(&exports.cc_data, frame_rate))?;
+    }
+    if let Some(ref path) = config.scc {
+        fs::write(path, captions::export_scc(&exports.cc_data, frame_rate))?;
+    }
     Ok(())
 }
 
@@ -370,14 +318,44 @@ fn main() -> std::io::Result<()> {
         .version(crate_version!())
         .about(crate_description!())
         .arg(Arg::from_usage("<INPUT>... 'Input AV1 bitstream files'").index(1))
-        .arg(Arg::from_usage("[v]... -v --verbose 'Show verbose log'"));
+        .arg(Arg::from_usage("[v]... -v --verbose 'Show verbose log'"))
+        .arg(Arg::from_usage(
+            "[op] --operating-point=[N] 'Select operating point (default 0)'",
+        ))
+        .arg(Arg::from_usage(
+            "[hdr10plus] --hdr10plus=[FILE] 'Export HDR10+ metadata as JSON file'",
+        ))
+        .arg(Arg::from_usage(
+            "[srt] --srt=[FILE] 'Export CEA-608 closed captions (CC1) as SRT file'",
+        ))
+        .arg(Arg::from_usage(
+            "[scc] --scc=[FILE] 'Export CEA-608 closed captions (field 1) as SCC file'",
+        ));
 
     // get commandline flags
     let matches = app.get_matches();
     let config = AppConfig {
         verbose: matches.occurrences_of("v"),
+        operating_point: if matches.is_present("op") {
+            value_t!(matches, "op", usize).unwrap_or_else(|e| e.exit())
+        } else {
+            0
+        },
+        hdr10plus: matches.value_of("hdr10plus").map(String::from),
+        srt: matches.value_of("srt").map(String::from),
+        scc: matches.value_of("scc").map(String::from),
     };
 
+    // export file holds the metadata of a single input file
+    let export = config.hdr10plus.is_some() || config.srt.is_some() || config.scc.is_some();
+    if export && matches.occurrences_of("INPUT") > 1 {
+        clap::Error::with_description(
+            "--hdr10plus, --srt and --scc accept only one INPUT",
+            clap::ErrorKind::ArgumentConflict,
+        )
+        .exit();
+    }
+
     for fname in matches.values_of("INPUT").unwrap() {
         process_file(fname, &config)?;
     }
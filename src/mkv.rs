@@ -11,6 +11,7 @@ const ELEMENT_EBML: u32 = 0x1A45DFA3; // EBML header
 const ELEMENT_SEGMENT: u32 = 0x18538067; // Segment
 const ELEMENT_SEEKHEAD: u32 = 0x114D9B74; // Meta Seek Information
 const ELEMENT_INFO: u32 = 0x1549A966; // Segment Information
+const ELEMENT_TIMECODESCALE: u32 = 0x2AD7B1; // Info/TimecodeScale
 const ELEMENT_CLUSTER: u32 = 0x1F43B675; // Cluster
 const ELEMENT_TIMECODE: u32 = 0xE7; // Cluster/Timecode
 const ELEMENT_SIMPLEBLOCK: u32 = 0xA3; // Cluster/SimpleBlock
@@ -20,6 +21,8 @@ const ELEMENT_TRACKENTRY: u32 = 0xAE; // Tracks/TrackEntry
 const ELEMENT_TRACKNUMBER: u32 = 0xD7; // Tracks/TrackEntry/TrackNumber
 const ELEMENT_TRACKTYPE: u32 = 0x83; // Tracks/TrackEntry/TrackType
 const ELEMENT_CODECID: u32 = 0x86; // Tracks/TrackEntry/CodecID
+const ELEMENT_CODECPRIVATE: u32 = 0x63A2; // Tracks/TrackEntry/CodecPrivate
+const ELEMENT_DEFAULTDURATION: u32 = 0x23E383; // Tracks/TrackEntry/DefaultDuration
 const ELEMENT_VIDEO: u32 = 0xE0; // Tracks/TrackEntry/Video
 const ELEMENT_PIXELWIDTH: u32 = 0xB0; // Tracks/TrackEntry/Video/PixelWidth
 const ELEMENT_PIXELHEIGHT: u32 = 0xBA; // Tracks/TrackEntry/Video/PixelHeight
@@ -28,6 +31,11 @@ const ELEMENT_CUES: u32 = 0x1C53BB6B; // Cueing Data
 // Codec ID
 pub const CODEC_V_AV1: &str = "V_AV1"; // video/AV1
 
+// SimpleBlock flags
+pub const BLOCK_FLAG_KEYFRAME: u8 = 0x80; // Keyframe
+
+const DEFAULT_TIMECODESCALE: u64 = 1_000_000; // [nsec]
+
 /// Element ID (1-4 bytes)
 fn read_elementid<R: io::Read>(mut reader: R) -> io::Result<u32> {
     let mut b0 = [0; 1];
@@ -94,7 +102,12 @@ fn read_datasize<R: io::Read>(reader: R) -> io::Result<i64> {
 
 /// Unsigned integer (1-8 bytes)
 fn read_uint<R: io::Read>(reader: R, len: i64) -> io::Result<u64> {
-    assert!(0 < len && len <= 8);
+    if !(0 < len && len <= 8) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Invalid unsigned integer size={}", len),
+        ));
+    }
     let mut buf = [0; 8];
     let rlen = reader.take(len as u64).read(&mut buf)?;
     if rlen < len as usize {
@@ -112,7 +125,12 @@ fn read_uint<R: io::Read>(reader: R, len: i64) -> io::Result<u64> {
 
 /// String (1-n bytes)
 fn read_string<R: io::Read>(reader: R, len: i64) -> io::Result<String> {
-    assert!(0 < len);
+    if len <= 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Invalid string size={}", len),
+        ));
+    }
     let mut value = String::new();
     reader.take(len as u64).read_to_string(&mut value)?;
     Ok(value)
@@ -123,6 +141,7 @@ fn read_string<R: io::Read>(reader: R, len: i64) -> io::Result<String> {
 ///
 #[derive(Debug)]
 pub struct Matroska {
+    timecode_scale: u64,
     tracks: Vec<TrackEntey>,
     clusters: Vec<Cluster>,
     curr_cluster: usize,
@@ -132,6 +151,7 @@ pub struct Matroska {
 impl Matroska {
     fn new() -> Self {
         Matroska {
+            timecode_scale: DEFAULT_TIMECODESCALE,
             tracks: Vec::new(),
             clusters: Vec::new(),
             curr_cluster: 0,
@@ -155,24 +175,54 @@ impl Matroska {
             .and_then(|t| t.setting.as_ref())
     }
 
+    /// get CodecPrivate data
+    pub fn get_codecprivate(&self, track_num: u64) -> Option<&[u8]> {
+        self.tracks
+            .iter()
+            .find(|t| t.track_num == track_num)
+            .and_then(|t| t.codec_private.as_ref())
+            .map(|v| v.as_slice())
+    }
+
+    /// get DefaultDuration [nsec]
+    pub fn get_defaultduration(&self, track_num: u64) -> Option<u64> {
+        self.tracks
+            .iter()
+            .find(|t| t.track_num == track_num)
+            .and_then(|t| t.default_duration)
+    }
+
+    /// get TimecodeScale [nsec]
+    pub fn get_timecodescale(&self) -> u64 {
+        self.timecode_scale
+    }
+
     /// read next block
     pub fn next_block<R: io::Read + io::Seek>(
         &mut self,
         mut reader: R,
     ) -> io::Result<Option<Block>> {
-        if self.curr_offset == 0 {
-            if self.clusters.len() <= self.curr_cluster {
-                return Ok(None); // end of clusters
-            }
-            self.curr_offset = self.clusters[self.curr_cluster].pos_begin;
-        }
-        reader.seek(SeekFrom::Start(self.curr_offset))?;
         loop {
+            let cluster = match self.clusters.get(self.curr_cluster) {
+                Some(cluster) => cluster,
+                None => return Ok(None), // end of clusters
+            };
+            if self.curr_offset == 0 {
+                self.curr_offset = cluster.pos_begin;
+            }
+            if cluster.pos_begin == 0 || cluster.pos_end <= self.curr_offset {
+                // end of cluster (or cluster without SimpleBlock)
+                self.curr_cluster += 1;
+                self.curr_offset = 0;
+                continue;
+            }
+            reader.seek(SeekFrom::Start(self.curr_offset))?;
+
             // seek to SimpleBlock element
             let node = read_elementid(&mut reader)?;
             let node_size = read_datasize(&mut reader)?;
             if node != ELEMENT_SIMPLEBLOCK {
-                reader.seek(SeekFrom::Current(node_size))?;
+                self.curr_offset = reader.stream_position()? + node_size as u64;
                 continue;
             }
 
@@ -187,7 +237,7 @@ impl Matroska {
             self.curr_offset = reader.stream_position()? + node_size;
             return Ok(Some(Block {
                 track_num: track_num as u64,
-                timecode: self.clusters[self.curr_cluster].timecode + (tc_offset as i64),
+                timecode: cluster.timecode + (tc_offset as i64),
                 flags: flags,
                 offset: self.curr_offset,
                 size: node_size,
@@ -204,6 +254,16 @@ impl Matroska {
                 ELEMENT_TRACKNUMBER => entry.track_num = read_uint(&mut reader, node_size)?,
                 ELEMENT_TRACKTYPE => entry.track_type = read_uint(&mut reader, node_size)?,
                 ELEMENT_CODECID => entry.codec_id = read_string(&mut reader, node_size)?,
+                ELEMENT_CODECPRIVATE => {
+                    let mut node_body = Vec::new();
+                    (&mut reader)
+                        .take(node_size as u64)
+                        .read_to_end(&mut node_body)?;
+                    entry.codec_private = Some(node_body);
+                }
+                ELEMENT_DEFAULTDURATION => {
+                    entry.default_duration = Some(read_uint(&mut reader, node_size)?)
+                }
                 ELEMENT_VIDEO => {
                     let mut node_body = Vec::with_capacity(node_size as usize);
                     node_body.resize(node_size as usize, 0);
@@ -236,6 +296,26 @@ impl Matroska {
         Ok(video)
     }
 
+    // Info element
+    fn read_info<R: io::Read + io::Seek>(
+        &mut self,
+        mut reader: R,
+        node_size: i64,
+    ) -> io::Result<()> {
+        let limit_pos = reader.stream_position()? + node_size as u64;
+        while reader.stream_position()? < limit_pos {
+            let node = read_elementid(&mut reader)?;
+            let node_size = read_datasize(&mut reader)?;
+            match node {
+                ELEMENT_TIMECODESCALE => self.timecode_scale = read_uint(&mut reader, node_size)?,
+                _ => {
+                    reader.seek(SeekFrom::Current(node_size))?;
+                }
+            }
+        }
+        Ok(())
+    }
+
     // Track element
     fn read_track<R: io::Read + io::Seek>(&mut self, mut reader: R) -> io::Result<()> {
         let mut pos = reader.stream_position()?;
@@ -287,7 +367,12 @@ impl Matroska {
                     }
                     reader.seek(SeekFrom::Current(node_size))?;
                 }
-                ELEMENT_BLOCKGROUP => unimplemented!("BlockGroup"),
+                ELEMENT_BLOCKGROUP => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "BlockGroup is not supported",
+                    ));
+                }
                 _ => {
                     reader.seek(SeekFrom::Current(node_size))?;
                 }
@@ -311,6 +396,8 @@ struct TrackEntey {
     track_num: u64,
     track_type: u64,
     codec_id: String,
+    codec_private: Option<Vec<u8>>,
+    default_duration: Option<u64>,
     setting: Option<VideoTrack>,
 }
 
@@ -375,6 +462,7 @@ pub fn open_mkvfile<R: io::Read + io::Seek>(mut reader: R) -> io::Result<Matrosk
     while let Ok(node) = read_elementid(&mut reader) {
         let node_size = read_datasize(&mut reader)?;
         match node {
+            ELEMENT_INFO => mkv.read_info(&mut reader, node_size)?,
             ELEMENT_TRACKS => mkv.read_track(&mut reader)?,
             ELEMENT_CLUSTER => mkv.read_cluster(&mut reader, node_size)?,
             _ => {
@@ -16,9 +16,13 @@ const BOX_MOVIEHEADER: [u8; 4] = *b"mvhd"; // Movie Header Box
 const BOX_TRACK: [u8; 4] = *b"trak"; // Track Box
 const BOX_TRACKHEADER: [u8; 4] = *b"tkhd"; // Track Header Box
 const BOX_MEDIA: [u8; 4] = *b"mdia"; // Media Box
+const BOX_MEDIAHEADER: [u8; 4] = *b"mdhd"; // Media Header Box
 const BOX_MEDIAINFORMATION: [u8; 4] = *b"minf"; // Media Information Box
 const BOX_SAMPLETABLE: [u8; 4] = *b"stbl"; // Sample Table Box
 const BOX_SAMPLEDESCRIPTION: [u8; 4] = *b"stsd"; // Sample Description Box
+const BOX_TIMETOSAMPLE: [u8; 4] = *b"stts"; // Decoding Time to Sample Box
+const BOX_COMPOSITIONOFFSET: [u8; 4] = *b"ctts"; // Composition Time to Sample Box
+const BOX_SYNCSAMPLE: [u8; 4] = *b"stss"; // Sync Sample Box
 const BOX_SAMPLETOCHUNK: [u8; 4] = *b"stsc"; // Sample To Chunk Box
 const BOX_SAMPLESIZE: [u8; 4] = *b"stsz"; // Sample Size Box
 const BOX_CHUNKOFFSET: [u8; 4] = *b"stco"; // Chunk Offset Box/32bit
@@ -103,7 +107,10 @@ fn read_box<R: io::Read>(mut reader: R) -> io::Result<(FCC, u64)> {
         }
         largesize - 16
     } else if size == 0 {
-        unimplemented!("box extends to end of file")
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Box extending to end of file is not supported",
+        ));
     } else {
         if size < 8 {
             return Err(io::Error::new(
@@ -279,6 +286,71 @@ fn parse_sampledescription<R: io::Read + io::Seek>(
     Ok(payload)
 }
 
+/// parse MediaHeaderBox payload, return timescale
+fn parse_mediaheader<R: io::Read>(mut reader: R) -> io::Result<u32> {
+    let version_flag = read_u32(&mut reader)?;
+    let timescale = if version_flag >> 24 == 1 {
+        let _creation_time = read_u64(&mut reader)?;
+        let _modification_time = read_u64(&mut reader)?;
+        let timescale = read_u32(&mut reader)?;
+        let _duration = read_u64(&mut reader)?;
+        timescale
+    } else {
+        let _creation_time = read_u32(&mut reader)?;
+        let _modification_time = read_u32(&mut reader)?;
+        let timescale = read_u32(&mut reader)?;
+        let _duration = read_u32(&mut reader)?;
+        timescale
+    };
+    let _language = read_u16(&mut reader)?;
+    let _pre_defined = read_u16(&mut reader)?;
+    Ok(timescale)
+}
+
+/// parse TimeToSampleBox payload
+fn parse_timetosample<R: io::Read>(mut reader: R) -> io::Result<Vec<(u32, u32)>> {
+    let _version_flag = read_u32(&mut reader)?;
+    let entry_count = read_u32(&mut reader)?;
+    let mut stts = Vec::with_capacity(entry_count as usize);
+    for _ in 0..entry_count {
+        let sample_count = read_u32(&mut reader)?;
+        let sample_delta = read_u32(&mut reader)?;
+        stts.push((sample_count, sample_delta));
+    }
+    Ok(stts)
+}
+
+/// parse CompositionOffsetBox payload
+fn parse_compositionoffset<R: io::Read>(mut reader: R) -> io::Result<Vec<(u32, i32)>> {
+    let version_flag = read_u32(&mut reader)?;
+    let entry_count = read_u32(&mut reader)?;
+    let mut ctts = Vec::with_capacity(entry_count as usize);
+    for _ in 0..entry_count {
+        let sample_count = read_u32(&mut reader)?;
+        let sample_offset = read_u32(&mut reader)?;
+        let sample_offset = if version_flag >> 24 == 0 {
+            // unsigned int(32) in version 0
+            sample_offset.min(i32::MAX as u32) as i32
+        } else {
+            sample_offset as i32
+        };
+        ctts.push((sample_count, sample_offset));
+    }
+    Ok(ctts)
+}
+
+/// parse SyncSampleBox payload
+fn parse_syncsample<R: io::Read>(mut reader: R) -> io::Result<Vec<u32>> {
+    let _version_flag = read_u32(&mut reader)?;
+    let entry_count = read_u32(&mut reader)?;
+    let mut stss = Vec::with_capacity(entry_count as usize);
+    for _ in 0..entry_count {
+        let sample_number = read_u32(&mut reader)?;
+        stss.push(sample_number);
+    }
+    Ok(stss)
+}
+
 /// parse SampleToChunkBox payload
 fn parse_sampletochunk<R: io::Read>(mut reader: R) -> io::Result<Vec<(u32, u32)>> {
     let _version_flag = read_u32(&mut reader)?;
@@ -337,7 +409,9 @@ fn parse_track<R: io::Read + io::Seek>(
 ) -> io::Result<bool> {
     let limit = reader.stream_position()? + size;
     let mut av1config = None;
+    let mut timescale = 0;
     let (mut stsc, mut stsz, mut stco) = (Vec::new(), Vec::new(), Vec::new());
+    let (mut stts, mut ctts, mut stss) = (Vec::new(), Vec::new(), None);
     loop {
         // read next Box
         let (boxtype, size) = match read_box(&mut reader) {
@@ -352,9 +426,21 @@ fn parse_track<R: io::Read + io::Seek>(
         };
         if boxtype == BOX_MEDIA || boxtype == BOX_MEDIAINFORMATION || boxtype == BOX_SAMPLETABLE {
             // parse nested Boxes
+        } else if boxtype == BOX_MEDIAHEADER {
+            // parse MediaHeaderBox
+            timescale = parse_mediaheader(&mut reader)?;
         } else if boxtype == BOX_SAMPLEDESCRIPTION {
             // parse SampleDescriptionBox
             av1config = parse_sampledescription(&mut reader)?;
+        } else if boxtype == BOX_TIMETOSAMPLE {
+            // parse TimeToSampleBox
+            stts = parse_timetosample(&mut reader)?;
+        } else if boxtype == BOX_COMPOSITIONOFFSET {
+            // parse CompositionOffsetBox
+            ctts = parse_compositionoffset(&mut reader)?;
+        } else if boxtype == BOX_SYNCSAMPLE {
+            // parse SyncSampleBox
+            stss = Some(parse_syncsample(&mut reader)?);
         } else if boxtype == BOX_SAMPLETOCHUNK {
             // parse SampleToChunkBox
             stsc = parse_sampletochunk(&mut reader)?;
@@ -376,6 +462,7 @@ fn parse_track<R: io::Read + io::Seek>(
         return Ok(false);
     }
     mp4.av1config = av1config;
+    mp4.timescale = timescale;
 
     // calculate Sample{pos,size} from stsc/stsz/stco
     let nsample = stsz.len();
@@ -386,7 +473,11 @@ fn parse_track<R: io::Read + io::Seek>(
         let mut pos = stco[stco_idx];
         for _ in 0..(stsc[stsc_idx].1) {
             let size = stsz[stsz_idx] as u64;
-            samples.push(Sample { pos, size });
+            samples.push(Sample {
+                pos,
+                size,
+                ..Default::default()
+            });
             pos += size;
             stsz_idx += 1;
         }
@@ -395,6 +486,25 @@ fn parse_track<R: io::Read + io::Seek>(
             stsc_idx += 1;
         }
     }
+
+    // calculate Sample{time,duration,composition_offset,is_sync} from stts/ctts/stss
+    let mut durations = stts
+        .iter()
+        .flat_map(|&(count, delta)| (0..count).map(move |_| delta));
+    let mut offsets = ctts
+        .iter()
+        .flat_map(|&(count, offset)| (0..count).map(move |_| offset));
+    let mut time = 0;
+    for (i, sample) in samples.iter_mut().enumerate() {
+        sample.time = time;
+        sample.duration = durations.next().unwrap_or(0);
+        sample.composition_offset = offsets.next().unwrap_or(0);
+        sample.is_sync = match stss {
+            Some(ref stss) => stss.binary_search(&(i as u32 + 1)).is_ok(),
+            None => true, // all samples are sync samples if stss is not present
+        };
+        time += sample.duration as u64;
+    }
     mp4.samples = samples;
 
     Ok(true)
@@ -403,10 +513,14 @@ fn parse_track<R: io::Read + io::Seek>(
 ///
 /// Sample
 ///
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct Sample {
     pub pos: u64,
     pub size: u64,
+    pub time: u64,               // decoding time in media timescale
+    pub duration: u32,           // sample_delta in media timescale
+    pub composition_offset: i32, // sample_offset in media timescale (composition time = time + offset)
+    pub is_sync: bool,           // sync sample
 }
 
 ///
@@ -416,6 +530,7 @@ pub struct Sample {
 pub struct IsoBmff {
     filetype: FileTypeBox,
     av1config: Option<(AV1SampleEntry, AV1CodecConfigurationBox)>,
+    timescale: u32,
     samples: Vec<Sample>,
 }
 
@@ -424,6 +539,7 @@ impl IsoBmff {
         IsoBmff {
             filetype,
             av1config: None,
+            timescale: 0,
             samples: Vec::new(),
         }
     }
@@ -438,6 +554,11 @@ impl IsoBmff {
         self.av1config.as_ref()
     }
 
+    /// get timescale of 'av01' track
+    pub fn get_timescale(&self) -> u32 {
+        self.timescale
+    }
+
     /// get 'av01' Samples
     pub fn get_samples(&self) -> &Vec<Sample> {
         &self.samples
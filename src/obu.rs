@@ -29,8 +29,10 @@ const MAX_TILE_AREA: u32 = 4096 * 2304; // Maximum area of a tile in units of lu
 const MAX_TILE_ROWS: u32 = 64; // Maximum number of tile rows
 const MAX_TILE_COLS: u32 = 64; // Maximum number of tile columns
 pub const NUM_REF_FRAMES: usize = 8; // Number of frames that can be stored for future reference
-const MAX_SEGMENTS: usize = 8; // Number of segments allowed in segmentation map
-const SEG_LVL_MAX: usize = 8; // Number of segment features
+pub const MAX_SEGMENTS: usize = 8; // Number of segments allowed in segmentation map
+pub const SEG_LVL_MAX: usize = 8; // Number of segment features
+const SEG_LVL_ALT_Q: usize = 0; // Index for quantizer segment feature
+const SEG_LVL_REF_FRAME: usize = 5; // Index for reference frame segment feature
 const SELECT_SCREEN_CONTENT_TOOLS: u8 = 2; // Value that indicates the allow_screen_content_tools syntax element is coded
 const SELECT_INTEGER_MV: u8 = 2; // Value that indicates the force_integer_mv syntax element is coded
 const RESTORATION_TILESIZE_MAX: usize = 256; // Maximum size of a loop restoration tile
@@ -93,10 +95,140 @@ const METADATA_TYPE_HDR_MDCV: u32 = 2;
 const METADATA_TYPE_SCALABILITY: u32 = 3;
 const METADATA_TYPE_ITUT_T35: u32 = 4;
 const METADATA_TYPE_TIMECODE: u32 = 5;
+const METADATA_TYPE_UNREGISTERED_FIRST: u32 = 6; // Unregistered user private
+const METADATA_TYPE_UNREGISTERED_LAST: u32 = 31;
 
 // scalability_mode_idc
 const SCALABILITY_SS: u8 = 14;
 
+/// Inter-layer prediction of predefined scalability mode
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum InterLayerPrediction {
+    None,     // simulcast (S*)
+    Always,   // spatial layers predict from lower layers (L*)
+    KeyFrame, // inter-layer prediction on key frames only (L*_KEY, L*_KEY_SHIFT)
+}
+
+///
+/// Predefined scalability mode
+///
+#[derive(Clone, Copy, Debug)]
+pub struct ScalabilityMode {
+    pub scalability_mode_idc: u8,
+    pub name: &'static str,
+    pub spatial_layers: u8,
+    pub temporal_layers: u8,
+    pub resolution_ratio: (u8, u8), // resolution ratio between adjacent spatial layers (2:1 or 1.5:1)
+    pub inter_layer_prediction: InterLayerPrediction,
+}
+
+macro_rules! scalability_mode {
+    ($idc:expr, $name:expr, $sl:expr, $tl:expr, $ratio:expr, $pred:ident) => {
+        ScalabilityMode {
+            scalability_mode_idc: $idc,
+            name: $name,
+            spatial_layers: $sl,
+            temporal_layers: $tl,
+            resolution_ratio: $ratio,
+            inter_layer_prediction: InterLayerPrediction::$pred,
+        }
+    };
+}
+
+/// Table of predefined scalability modes (6.7.5 Scalability metadata semantics)
+pub const SCALABILITY_MODES: [ScalabilityMode; 28] = [
+    scalability_mode!(0, "L1T2", 1, 2, (1, 1), None),
+    scalability_mode!(1, "L1T3", 1, 3, (1, 1), None),
+    scalability_mode!(2, "L2T1", 2, 1, (2, 1), Always),
+    scalability_mode!(3, "L2T2", 2, 2, (2, 1), Always),
+    scalability_mode!(4, "L2T3", 2, 3, (2, 1), Always),
+    scalability_mode!(5, "S2T1", 2, 1, (2, 1), None),
+    scalability_mode!(6, "S2T2", 2, 2, (2, 1), None),
+    scalability_mode!(7, "S2T3", 2, 3, (2, 1), None),
+    scalability_mode!(8, "L2T1h", 2, 1, (3, 2), Always),
+    scalability_mode!(9, "L2T2h", 2, 2, (3, 2), Always),
+    scalability_mode!(10, "L2T3h", 2, 3, (3, 2), Always),
+    scalability_mode!(11, "S2T1h", 2, 1, (3, 2), None),
+    scalability_mode!(12, "S2T2h", 2, 2, (3, 2), None),
+    scalability_mode!(13, "S2T3h", 2, 3, (3, 2), None),
+    // 14: SCALABILITY_SS
+    scalability_mode!(15, "L3T1", 3, 1, (2, 1), Always),
+    scalability_mode!(16, "L3T2", 3, 2, (2, 1), Always),
+    scalability_mode!(17, "L3T3", 3, 3, (2, 1), Always),
+    scalability_mode!(18, "S3T1", 3, 1, (2, 1), None),
+    scalability_mode!(19, "S3T2", 3, 2, (2, 1), None),
+    scalability_mode!(20, "S3T3", 3, 3, (2, 1), None),
+    scalability_mode!(21, "L3T2_KEY", 3, 2, (2, 1), KeyFrame),
+    scalability_mode!(22, "L3T3_KEY", 3, 3, (2, 1), KeyFrame),
+    scalability_mode!(23, "L4T5_KEY", 4, 5, (2, 1), KeyFrame),
+    scalability_mode!(24, "L4T7_KEY", 4, 7, (2, 1), KeyFrame),
+    scalability_mode!(25, "L3T2_KEY_SHIFT", 3, 2, (2, 1), KeyFrame),
+    scalability_mode!(26, "L3T3_KEY_SHIFT", 3, 3, (2, 1), KeyFrame),
+    scalability_mode!(27, "L4T5_KEY_SHIFT", 4, 5, (2, 1), KeyFrame),
+    scalability_mode!(28, "L4T7_KEY_SHIFT", 4, 7, (2, 1), KeyFrame),
+];
+
+///
+/// OBU parse error kind
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ParseErrorKind {
+    Truncated,                 // OBU payload ends in the middle of syntax structure
+    Unsupported(&'static str), // feature which is not supported by this parser
+    Conformance(&'static str), // violation of bitstream conformance requirement
+    InvalidValue { element: &'static str, value: i64 }, // invalid or reserved value of syntax element
+}
+
+impl fmt::Display for ParseErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ParseErrorKind::Truncated => write!(f, "truncated"),
+            ParseErrorKind::Unsupported(feature) => write!(f, "unsupported {}", feature),
+            ParseErrorKind::Conformance(rule) => write!(f, "conformance violation: {}", rule),
+            ParseErrorKind::InvalidValue { element, value } => {
+                write!(f, "invalid {}={}", element, value)
+            }
+        }
+    }
+}
+
+///
+/// OBU parse error
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ParseError {
+    pub kind: ParseErrorKind,
+    pub obu_index: usize,  // index of OBU in the stream, set by the caller
+    pub bit_position: u64, // bit position in OBU payload
+}
+
+impl ParseError {
+    pub fn new(kind: ParseErrorKind, bit_position: u64) -> Self {
+        ParseError {
+            kind,
+            obu_index: 0,
+            bit_position,
+        }
+    }
+
+    /// set the index of OBU in the stream
+    pub fn with_obu_index(self, obu_index: usize) -> Self {
+        ParseError { obu_index, ..self }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "OBU#{} bit {}: {}",
+            self.obu_index, self.bit_position, self.kind
+        )
+    }
+}
+
+impl std::error::Error for ParseError {}
+
 ///
 /// OBU(Open Bitstream Unit)
 ///
@@ -141,6 +273,70 @@ impl fmt::Display for Obu {
     }
 }
 
+///
+/// OBU with borrowed payload
+///
+#[derive(Debug)]
+pub struct ObuRef<'a> {
+    pub obu: Obu,
+    pub payload: &'a [u8], // obu_size bytes of OBU payload
+}
+
+///
+/// OBU iterator over byte buffer
+///
+/// `data` is the enclosing unit of OBUs (temporal unit, IVF frame, MP4 sample, etc.),
+/// OBU without obu_size field extends to the end of `data`.
+/// The bit position of parse error is relative to the beginning of `data`.
+///
+pub struct ObuIter<'a> {
+    data: &'a [u8],
+    pos: usize, // byte position of the next OBU
+}
+
+impl<'a> ObuIter<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        ObuIter { data, pos: 0 }
+    }
+}
+
+impl<'a> Iterator for ObuIter<'a> {
+    type Item = Result<ObuRef<'a>, ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.pos >= self.data.len() {
+            return None;
+        }
+        let data = &self.data[self.pos..];
+        if data.len() > u32::MAX as usize {
+            // open_bitstream_unit(sz) is limited to 32-bit size
+            let err = ParseError::new(
+                ParseErrorKind::InvalidValue {
+                    element: "sz",
+                    value: data.len() as i64,
+                },
+                self.pos as u64 * 8,
+            );
+            self.pos = self.data.len();
+            return Some(Err(err));
+        }
+        let mut br = BitReader::new(data);
+        match read_obu_header(&mut br, data.len() as u32) {
+            Ok(obu) => {
+                let payload_pos = obu.header_len as usize;
+                let payload = &data[payload_pos..payload_pos + obu.obu_size as usize];
+                self.pos += payload_pos + obu.obu_size as usize;
+                Some(Ok(ObuRef { obu, payload }))
+            }
+            Err(err) => {
+                let bit_position = self.pos as u64 * 8 + err.bit_position;
+                self.pos = self.data.len();
+                Some(Err(ParseError::new(err.kind, bit_position)))
+            }
+        }
+    }
+}
+
 // Color config
 #[derive(Clone, Copy, Debug, Default)]
 pub struct ColorConfig {
@@ -173,17 +369,32 @@ pub struct TimingInfo {
 ///
 #[derive(Clone, Copy, Debug, Default)]
 pub struct OperatingPoint {
-    pub operating_point_idc: u16, // f(12)
-    pub seq_level_idx: u8,        // f(5)
-    pub seq_tier: u8,             // f(1)
+    pub operating_point_idc: u16,                           // f(12)
+    pub seq_level_idx: u8,                                  // f(5)
+    pub seq_tier: u8,                                       // f(1)
+    pub decoder_model_present_for_this_op: bool,            // f(1)
+    pub operating_parameters_info: OperatingParametersInfo, // operating_parameters_info()
+    pub initial_display_delay_present_for_this_op: bool,    // f(1)
+    pub initial_display_delay_minus_1: u8,                  // f(4)
 }
 
+/// Operating parameters info
+#[derive(Clone, Copy, Debug, Default)]
+pub struct OperatingParametersInfo {
+    // operating_parameters_info()
+    pub decoder_buffer_delay: u32, // f(n)
+    pub encoder_buffer_delay: u32, // f(n)
+    pub low_delay_mode_flag: bool, // f(1)
+}
+
+/// Decoder model info
 #[derive(Clone, Copy, Debug, Default)]
 pub struct DecoderModelInfo {
-    pub buffer_delay_length: u8,
-    pub num_units_in_decoding_tick: u32,
-    pub buffer_removal_time: u8,
-    pub frame_presentation_time_length: u8,
+    // decoder_model_info()
+    pub buffer_delay_length: u8,            // f(5)
+    pub num_units_in_decoding_tick: u32,    // f(32)
+    pub buffer_removal_time_length: u8,     // f(5)
+    pub frame_presentation_time_length: u8, // f(5)
 }
 
 ///
@@ -229,7 +440,7 @@ pub struct SequenceHeader {
 }
 
 /// Frame size
-#[derive(Debug, Default)]
+#[derive(Clone, Debug, Default)]
 pub struct FrameSize {
     // frame_size()
     pub frame_width: u32,  // FrameWidth
@@ -240,7 +451,7 @@ pub struct FrameSize {
 }
 
 /// Render size
-#[derive(Debug, Default)]
+#[derive(Clone, Debug, Default)]
 pub struct RenderSize {
     // render_size()
     pub render_width: u32,  // RenderWidth
@@ -248,12 +459,13 @@ pub struct RenderSize {
 }
 
 /// Loop filter params
-#[derive(Debug, Default)]
+#[derive(Clone, Debug, Default)]
 pub struct LoopFilterParams {
     // loop_filter_params()
     pub loop_filter_level: [u8; 4],                          // f(6)
     pub loop_filter_sharpness: u8,                           // f(3)
     pub loop_filter_delta_enabled: bool,                     // f(1)
+    pub loop_filter_delta_update: bool,                      // f(1)
     pub loop_filter_ref_deltas: [i32; TOTAL_REFS_PER_FRAME], // su(1+6)
     pub loop_filter_mode_deltas: [i32; 2],                   // su(1+6)
 }
@@ -261,15 +473,17 @@ pub struct LoopFilterParams {
 /// Tile info
 #[derive(Debug, Default, Clone, Copy)]
 pub struct TileInfo {
-    pub tile_cols: u16, // TileCols
-    pub tile_rows: u16, // TileRows
+    pub tile_cols: u16,        // TileCols
+    pub tile_rows: u16,        // TileRows
+    pub tile_cols_log2: usize, // TileColsLog2
+    pub tile_rows_log2: usize, // TileRowsLog2
     // tile_info()
     pub context_update_tile_id: u32, // f(TileRowsLog2+TileColsLog2)
     pub tile_size_bytes: usize,      // TileSizeBytes
 }
 
 /// Quantization params
-#[derive(Debug, Default)]
+#[derive(Clone, Debug, Default)]
 pub struct QuantizationParams {
     pub deltaq_y_dc: i32, // DeltaQYDc
     pub deltaq_u_dc: i32, // DeltaQUDc
@@ -285,17 +499,21 @@ pub struct QuantizationParams {
 }
 
 /// Segmentation params
-#[derive(Debug, Default)]
+#[derive(Clone, Debug, Default)]
 pub struct SegmentationParams {
     // segmentation_params()
-    pub segmentation_enabled: bool,         // f(1)
-    pub segmentation_update_map: bool,      // f(1)
-    pub segmentation_temporal_update: bool, // f(1)
-    pub segmentation_update_data: bool,     // f(1)
+    pub segmentation_enabled: bool,                           // f(1)
+    pub segmentation_update_map: bool,                        // f(1)
+    pub segmentation_temporal_update: bool,                   // f(1)
+    pub segmentation_update_data: bool,                       // f(1)
+    pub feature_enabled: [[bool; SEG_LVL_MAX]; MAX_SEGMENTS], // FeatureEnabled[i][j]
+    pub feature_data: [[i32; SEG_LVL_MAX]; MAX_SEGMENTS],     // FeatureData[i][j]
+    pub seg_id_pre_skip: bool,                                // SegIdPreSkip
+    pub last_active_seg_id: u8,                               // LastActiveSegId
 }
 
 /// Quantizer index delta parameters
-#[derive(Debug, Default)]
+#[derive(Clone, Debug, Default)]
 pub struct DeltaQParams {
     // delta_q_params()
     pub delta_q_present: bool, // f(1)
@@ -303,7 +521,7 @@ pub struct DeltaQParams {
 }
 
 /// Loop filter delta parameters
-#[derive(Debug, Default)]
+#[derive(Clone, Debug, Default)]
 pub struct DeltaLfParams {
     // delta_lf_params()
     pub delta_lf_present: bool, // f(1)
@@ -312,7 +530,7 @@ pub struct DeltaLfParams {
 }
 
 /// CDEF params
-#[derive(Debug, Default)]
+#[derive(Clone, Debug, Default)]
 pub struct CdefParams {
     // cdef_params()
     pub cdef_damping: u8,              // f(2)
@@ -324,7 +542,7 @@ pub struct CdefParams {
 }
 
 /// Loop restoration params
-#[derive(Debug, Default)]
+#[derive(Clone, Debug, Default)]
 pub struct LrParams {
     pub uses_lr: bool,                   // UsesLr
     pub frame_restoration_type: [u8; 3], // FrameRestorationType[]
@@ -332,7 +550,7 @@ pub struct LrParams {
 }
 
 /// Skip mode params
-#[derive(Debug, Default)]
+#[derive(Clone, Debug, Default)]
 pub struct SkipModeParams {
     pub skip_mode_frame: [u8; 2], // SkipModeFrame[]
     // skip_mode_params()
@@ -340,7 +558,7 @@ pub struct SkipModeParams {
 }
 
 /// Global motion params
-#[derive(Debug, Default)]
+#[derive(Clone, Debug, Default)]
 pub struct GlobalMotionParams {
     pub gm_type: [u8; NUM_REF_FRAMES],              // GmType[]
     pub gm_params: [[i32; 6]; NUM_REF_FRAMES],      // gm_params[]
@@ -350,55 +568,81 @@ pub struct GlobalMotionParams {
 ///
 /// Frame header OBU
 ///
-#[derive(Debug, Default)]
+#[derive(Clone, Debug, Default)]
 pub struct FrameHeader {
     // uncompressed_header()
-    pub show_existing_frame: bool,                // f(1)
-    pub frame_to_show_map_idx: u8,                // f(3)
-    pub display_frame_id: u16,                    // f(idLen)
-    pub frame_type: u8,                           // f(2)
-    pub frame_is_intra: bool,                     // FrameIsIntra
-    pub show_frame: bool,                         // f(1)
-    pub showable_frame: bool,                     // f(1)
-    pub error_resilient_mode: bool,               // f(1)
-    pub disable_cdf_update: bool,                 // f(1)
-    pub allow_screen_content_tools: bool,         // f(1)
-    pub force_integer_mv: bool,                   // f(1)
-    pub current_frame_id: u16,                    // f(idLen)
-    pub frame_size_override_flag: bool,           // f(1)
-    pub order_hint: u8,                           // f(OrderHintBits)
-    pub primary_ref_frame: u8,                    // f(3)
-    pub refresh_frame_flags: u8,                  // f(8)
-    pub ref_order_hint: [u8; NUM_REF_FRAMES],     // f(OrderHintBits)
-    pub frame_size: FrameSize,                    // frame_size()
-    pub render_size: RenderSize,                  // render_size()
-    pub allow_intrabc: bool,                      // f(1)
-    pub last_frame_idx: u8,                       // f(3)
-    pub gold_frame_idx: u8,                       // f(3)
-    pub ref_frame_idx: [u8; NUM_REF_FRAMES],      // f(3)
-    pub allow_high_precision_mv: bool,            // f(1)
-    pub interpolation_filter: u8,                 // f(2)
-    pub is_motion_mode_switchable: bool,          // f(1)
-    pub use_ref_frame_mvs: bool,                  // f(1)
-    pub disable_frame_end_update_cdf: bool,       // f(1)
-    pub order_hints: [u8; NUM_REF_FRAMES],        // OrderHints
-    pub tile_info: TileInfo,                      // tile_info()
-    pub quantization_params: QuantizationParams,  // quantization_params()
-    pub segmentation_params: SegmentationParams,  // segmentation_params()
-    pub delta_q_params: DeltaQParams,             // delta_q_params()
-    pub delta_lf_params: DeltaLfParams,           // delta_lf_params()
-    pub coded_lossless: bool,                     // CodedLossless
-    pub all_lossless: bool,                       // AllLossless
-    pub loop_filter_params: LoopFilterParams,     // loop_filter_params()
-    pub cdef_params: CdefParams,                  // cdef_params()
-    pub lr_params: LrParams,                      // lr_params()
-    pub tx_mode: u8,                              // TxMode
-    pub skip_mode_params: SkipModeParams,         // skip_mode_params()
-    pub global_motion_params: GlobalMotionParams, // global_motion_params()
-    pub film_grain_params: FilmGrainParams,       // film_grain_params()
-    pub reference_select: bool,                   // f(1)
-    pub allow_warped_motion: bool,                // f(1)
-    pub reduced_tx_set: bool,                     // f(1)
+    pub show_existing_frame: bool,                   // f(1)
+    pub frame_to_show_map_idx: u8,                   // f(3)
+    pub frame_presentation_time: u32,                // f(n)
+    pub display_frame_id: u16,                       // f(idLen)
+    pub frame_type: u8,                              // f(2)
+    pub frame_is_intra: bool,                        // FrameIsIntra
+    pub show_frame: bool,                            // f(1)
+    pub showable_frame: bool,                        // f(1)
+    pub error_resilient_mode: bool,                  // f(1)
+    pub disable_cdf_update: bool,                    // f(1)
+    pub allow_screen_content_tools: bool,            // f(1)
+    pub force_integer_mv: bool,                      // f(1)
+    pub current_frame_id: u16,                       // f(idLen)
+    pub frame_size_override_flag: bool,              // f(1)
+    pub order_hint: u8,                              // f(OrderHintBits)
+    pub primary_ref_frame: u8,                       // f(3)
+    pub buffer_removal_time_present_flag: bool,      // f(1)
+    pub buffer_removal_time: Vec<Option<u32>>,       // f(n)
+    pub refresh_frame_flags: u8,                     // f(8)
+    pub ref_order_hint: [u8; NUM_REF_FRAMES],        // f(OrderHintBits)
+    pub frame_size: FrameSize,                       // frame_size()
+    pub render_size: RenderSize,                     // render_size()
+    pub allow_intrabc: bool,                         // f(1)
+    pub last_frame_idx: u8,                          // f(3)
+    pub gold_frame_idx: u8,                          // f(3)
+    pub ref_frame_idx: [u8; NUM_REF_FRAMES],         // f(3)
+    pub allow_high_precision_mv: bool,               // f(1)
+    pub interpolation_filter: u8,                    // f(2)
+    pub is_motion_mode_switchable: bool,             // f(1)
+    pub use_ref_frame_mvs: bool,                     // f(1)
+    pub disable_frame_end_update_cdf: bool,          // f(1)
+    pub order_hints: [u8; NUM_REF_FRAMES],           // OrderHints
+    pub ref_frame_sign_bias: [bool; NUM_REF_FRAMES], // RefFrameSignBias (true=backward reference)
+    pub tile_info: TileInfo,                         // tile_info()
+    pub quantization_params: QuantizationParams,     // quantization_params()
+    pub segmentation_params: SegmentationParams,     // segmentation_params()
+    pub delta_q_params: DeltaQParams,                // delta_q_params()
+    pub delta_lf_params: DeltaLfParams,              // delta_lf_params()
+    pub coded_lossless: bool,                        // CodedLossless
+    pub lossless_array: [bool; MAX_SEGMENTS],        // LosslessArray[segmentId]
+    pub seg_qm_level: [[u8; MAX_SEGMENTS]; 3],       // SegQMLevel[plane][segmentId]
+    pub all_lossless: bool,                          // AllLossless
+    pub loop_filter_params: LoopFilterParams,        // loop_filter_params()
+    pub cdef_params: CdefParams,                     // cdef_params()
+    pub lr_params: LrParams,                         // lr_params()
+    pub tx_mode: u8,                                 // TxMode
+    pub skip_mode_params: SkipModeParams,            // skip_mode_params()
+    pub global_motion_params: GlobalMotionParams,    // global_motion_params()
+    pub film_grain_params: FilmGrainParams,          // film_grain_params()
+    pub reference_select: bool,                      // f(1)
+    pub allow_warped_motion: bool,                   // f(1)
+    pub reduced_tx_set: bool,                        // f(1)
+}
+
+///
+/// Tile group OBU
+///
+#[derive(Debug, Default)]
+pub struct TileGroup {
+    pub tile_start_and_end_present_flag: bool, // f(1)
+    pub tg_start: u16,                         // f(tileBits)
+    pub tg_end: u16,                           // f(tileBits)
+    pub tiles: Vec<TileLocation>,              // tiles in tg_start..=tg_end
+}
+
+/// Tile location in tile group OBU
+#[derive(Debug, Default)]
+pub struct TileLocation {
+    pub tile_row: u16, // tileRow
+    pub tile_col: u16, // tileCol
+    pub offset: u32,   // byte offset from the beginning of tile_group_obu()
+    pub size: u32,     // tileSize
 }
 
 ///
@@ -419,10 +663,12 @@ pub struct TileListEntry {
     pub anchor_tile_row: u8,         // f(8)
     pub anchor_tile_col: u8,         // f(8)
     pub tile_data_size_minus_1: u16, // f(16)
+    pub offset: u32, // byte offset of coded tile data from the beginning of tile_list_obu()
+    pub size: u32,   // tile_data_size_minus_1 + 1
 }
 
 /// Film grain synthesis parameters
-#[derive(Debug, Default)]
+#[derive(Clone, Debug, Default)]
 pub struct FilmGrainParams {
     pub apply_grain: bool,              // f(1)
     pub grain_seed: u16,                // f(16)
@@ -455,7 +701,7 @@ pub struct FilmGrainParams {
     pub clip_to_restricted_range: bool, // f(1)
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone)]
 pub struct ScalabilityStructure {
     pub spatial_layers_cnt_minus_1: u8,                // f(2)
     pub spatial_layer_dimensions_present_flag: bool,   // f(1)
@@ -481,6 +727,14 @@ pub enum MetadataObu {
     Scalability(ScalabilityMetadata),
     ItutT35(ItutT35Metadata),
     Timecode(TimecodeMetadata),
+    Unregistered {
+        metadata_type: u32,
+        payload: Vec<u8>,
+    },
+    Reserved {
+        metadata_type: u32,
+        payload: Vec<u8>,
+    },
 }
 
 #[derive(Debug, Default)]
@@ -499,12 +753,38 @@ pub struct HdrMdcvMetadata {
     pub luminance_min: u32,              // f(32)
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone)]
 pub struct ScalabilityMetadata {
     pub scalability_mode_idc: u8,                            // f(8)
     pub scalability_structure: Option<ScalabilityStructure>, // scalability_structure()
 }
 
+impl ScalabilityMetadata {
+    /// predefined scalability mode for scalability_mode_idc (None for SCALABILITY_SS or reserved)
+    pub fn mode(&self) -> Option<&'static ScalabilityMode> {
+        SCALABILITY_MODES
+            .iter()
+            .find(|m| m.scalability_mode_idc == self.scalability_mode_idc)
+    }
+
+    /// number of (spatial layers, temporal layers) declared by the predefined mode or scalability_structure()
+    pub fn layers(&self) -> Option<(u8, u8)> {
+        if let Some(mode) = self.mode() {
+            return Some((mode.spatial_layers, mode.temporal_layers));
+        }
+        let ss = self.scalability_structure.as_ref()?;
+        let temporal_layers = if ss.temporal_group_description_present_flag {
+            ss.temporal_group_temporal_id
+                .iter()
+                .max()
+                .map_or(1, |t| t + 1)
+        } else {
+            8 // temporal layers are not described, any temporal_id(3bit) is allowed
+        };
+        Some((ss.spatial_layers_cnt_minus_1 + 1, temporal_layers))
+    }
+}
+
 #[derive(Debug, Default)]
 pub struct ItutT35Metadata {
     pub itu_t_t35_country_code: u8,                        // f(8)
@@ -560,29 +840,56 @@ pub fn leb128<R: io::Read>(bs: &mut R) -> io::Result<(u32, u32)> {
     Ok((leb128bytes, value as u32))
 }
 
+/// remove trailing_bits() and return payload bits padded with zero bits
+pub fn strip_trailing_bits(payload: &[u8]) -> Vec<u8> {
+    let mut bits = payload.to_vec();
+    while let Some(&last) = bits.last() {
+        if last == 0 {
+            bits.pop();
+            continue;
+        }
+        // clear trailing_one_bit
+        let last = last & (last - 1);
+        bits.pop();
+        if last != 0 {
+            bits.push(last);
+        }
+        break;
+    }
+    bits
+}
+
+/// return the first `size` bits of `payload` padded with zero bits, or None if `payload` is too short
+pub fn leading_bits(payload: &[u8], size: u64) -> Option<Vec<u8>> {
+    let mut bits = payload.get(0..size.div_ceil(8) as usize)?.to_vec();
+    if let Some(last) = bits.last_mut() {
+        if !size.is_multiple_of(8) {
+            *last &= 0xff << (8 - size % 8);
+        }
+    }
+    Some(bits)
+}
+
 ///
 /// parse trailing_bits()
 ///
-fn trailing_bits<R: io::Read>(br: &mut BitReader<R>) -> Option<()> {
+fn trailing_bits(br: &mut BitReader) -> Result<(), ParseError> {
     let trailing_one_bit = br.f::<u8>(1)?;
     if trailing_one_bit != 1 {
-        return None;
+        return Err(br.error(ParseErrorKind::Conformance("trailing_one_bit")));
     }
-    while let Some(trailing_zero_bit) = br.f::<u8>(1) {
+    while let Ok(trailing_zero_bit) = br.f::<u8>(1) {
         if trailing_zero_bit != 0 {
-            return None;
+            return Err(br.error(ParseErrorKind::Conformance("trailing_zero_bit")));
         }
     }
-    Some(())
+    Ok(())
 }
 
 ///
 /// parse color_config()
 ///
-fn parse_color_config<R: io::Read>(
-    br: &mut BitReader<R>,
-    sh: &SequenceHeader,
-) -> Option<ColorConfig> {
+fn parse_color_config(br: &mut BitReader, sh: &SequenceHeader) -> Result<ColorConfig, ParseError> {
     let mut cc = ColorConfig::default();
 
     let high_bitdepth = br.f::<bool>(1)?; // f(1)
@@ -614,7 +921,7 @@ fn parse_color_config<R: io::Read>(
         cc.subsampling_y = 1;
         cc.chroma_sample_position = CSP_UNKNOWN;
         cc.separate_uv_delta_q = false;
-        return Some(cc);
+        return Ok(cc);
     } else if cc.color_primaries == CP_BT_709
         && cc.transfer_characteristics == TC_SRGB
         && cc.matrix_coefficients == MC_IDENTITY
@@ -622,7 +929,7 @@ fn parse_color_config<R: io::Read>(
         cc.color_range = true;
         cc.subsampling_x = 0;
         cc.subsampling_y = 0;
-        return Some(cc);
+        return Ok(cc);
     } else {
         cc.color_range = br.f::<bool>(1)?; // f(1)
         if sh.seq_profile == 0 {
@@ -650,33 +957,63 @@ fn parse_color_config<R: io::Read>(
     }
     cc.separate_uv_delta_q = br.f::<bool>(1)?; // f(1)
 
-    Some(cc)
+    Ok(cc)
 }
 
 ///
 /// parse timing_info()
 ///
-fn parse_timing_info<R: io::Read>(br: &mut BitReader<R>) -> Option<TimingInfo> {
+fn parse_timing_info(br: &mut BitReader) -> Result<TimingInfo, ParseError> {
     let mut ti = TimingInfo::default();
 
     ti.num_units_in_display_tick = br.f::<u32>(32)?; // f(32)
     ti.time_scale = br.f::<u32>(32)?; // f(32)
     ti.equal_picture_interval = br.f::<bool>(1)?; // f(1)
     if ti.equal_picture_interval {
-        ti.num_ticks_per_picture = br.uvlc()? as u32 + 1;
+        ti.num_ticks_per_picture = (br.uvlc()? as u32).saturating_add(1);
     }
 
-    Some(ti)
+    Ok(ti)
+}
+
+///
+/// parse temporal_point_info(), return frame_presentation_time
+///
+fn parse_temporal_point_info(br: &mut BitReader, sh: &SequenceHeader) -> Result<u32, ParseError> {
+    let decoder_model_info = sh.decoder_model_info.as_ref().ok_or_else(|| {
+        br.error(ParseErrorKind::Conformance(
+            "temporal_point_info() requires decoder_model_info()",
+        ))
+    })?;
+    let n = decoder_model_info.frame_presentation_time_length as usize;
+    br.f::<u32>(n) // f(n)
+}
+
+///
+/// parse operating_parameters_info()
+///
+fn parse_operating_parameters_info(
+    br: &mut BitReader,
+    dmi: &DecoderModelInfo,
+) -> Result<OperatingParametersInfo, ParseError> {
+    let mut opi = OperatingParametersInfo::default();
+
+    let n = dmi.buffer_delay_length as usize;
+    opi.decoder_buffer_delay = br.f::<u32>(n)?; // f(n)
+    opi.encoder_buffer_delay = br.f::<u32>(n)?; // f(n)
+    opi.low_delay_mode_flag = br.f::<bool>(1)?; // f(1)
+
+    Ok(opi)
 }
 
 ///
 /// parse frame_size() (include superres_params())
 ///
-fn parse_frame_size<R: io::Read>(
-    br: &mut BitReader<R>,
+fn parse_frame_size(
+    br: &mut BitReader,
     sh: &SequenceHeader,
     fh: &FrameHeader,
-) -> Option<FrameSize> {
+) -> Result<FrameSize, ParseError> {
     let mut fs = FrameSize::default();
 
     // frame_size()
@@ -687,7 +1024,20 @@ fn parse_frame_size<R: io::Read>(
         fs.frame_width = sh.max_frame_width;
         fs.frame_height = sh.max_frame_height;
     }
-    // superres_params()
+    parse_superres_params(br, sh, &mut fs)?; // superres_params()
+                                             // compute_image_size()
+
+    Ok(fs)
+}
+
+///
+/// parse superres_params()
+///
+fn parse_superres_params(
+    br: &mut BitReader,
+    sh: &SequenceHeader,
+    fs: &mut FrameSize,
+) -> Result<(), ParseError> {
     if sh.enable_superres {
         fs.use_superres = br.f::<bool>(1)?; // f(1)
     } else {
@@ -703,15 +1053,47 @@ fn parse_frame_size<R: io::Read>(
     fs.upscaled_width = fs.frame_width;
     fs.frame_width = ((fs.upscaled_width as usize * SUPERRES_NUM + (supreres_denom / 2))
         / supreres_denom) as u32;
-    // compute_image_size()
 
-    Some(fs)
+    Ok(())
+}
+
+///
+/// parse frame_size_with_refs()
+///
+fn parse_frame_size_with_refs(
+    br: &mut BitReader,
+    sh: &SequenceHeader,
+    fh: &FrameHeader,
+    rfman: &av1::RefFrameManager,
+) -> Result<(FrameSize, RenderSize), ParseError> {
+    for i in 0..REFS_PER_FRAME {
+        let found_ref = br.f::<bool>(1)?; // f(1)
+        if found_ref {
+            let idx = fh.ref_frame_idx[i] as usize;
+            let mut fs = FrameSize {
+                frame_width: rfman.ref_upscaled_width[idx],
+                frame_height: rfman.ref_frame_height[idx],
+                ..Default::default()
+            };
+            let rs = RenderSize {
+                render_width: rfman.ref_render_width[idx],
+                render_height: rfman.ref_render_height[idx],
+            };
+            parse_superres_params(br, sh, &mut fs)?; // superres_params()
+                                                     // compute_image_size()
+            return Ok((fs, rs));
+        }
+    }
+    let fs = parse_frame_size(br, sh, fh)?; // frame_size()
+    let rs = parse_render_size(br, &fs)?; // render_size()
+
+    Ok((fs, rs))
 }
 
 ///
 /// parse render_size()
 ///
-fn parse_render_size<R: io::Read>(br: &mut BitReader<R>, fs: &FrameSize) -> Option<RenderSize> {
+fn parse_render_size(br: &mut BitReader, fs: &FrameSize) -> Result<RenderSize, ParseError> {
     let mut rs = RenderSize::default();
 
     let render_and_frame_size_different = br.f::<bool>(1)?; // f(1)
@@ -723,11 +1105,11 @@ fn parse_render_size<R: io::Read>(br: &mut BitReader<R>, fs: &FrameSize) -> Opti
         rs.render_height = fs.frame_height;
     }
 
-    Some(rs)
+    Ok(rs)
 }
 
 /// read_interpolation_filter()
-fn read_interpolation_filter<R: io::Read>(br: &mut BitReader<R>) -> Option<u8> {
+fn read_interpolation_filter(br: &mut BitReader) -> Result<u8, ParseError> {
     let is_filter_switchable = br.f::<bool>(1)?; // f(1)
     let interpolation_filter;
     if is_filter_switchable {
@@ -736,18 +1118,23 @@ fn read_interpolation_filter<R: io::Read>(br: &mut BitReader<R>) -> Option<u8> {
         interpolation_filter = br.f::<u8>(2)?; // f(2)
     }
 
-    Some(interpolation_filter)
+    Ok(interpolation_filter)
 }
 
 ///
 /// parse loop_filter_params()
 ///
-fn parse_loop_filter_params<R: io::Read>(
-    br: &mut BitReader<R>,
+fn parse_loop_filter_params(
+    br: &mut BitReader,
     cc: &ColorConfig,
     fh: &FrameHeader,
-) -> Option<LoopFilterParams> {
-    let mut lfp = LoopFilterParams::default();
+) -> Result<LoopFilterParams, ParseError> {
+    // deltas are inherited from setup_past_independence() or load_previous()
+    let mut lfp = LoopFilterParams {
+        loop_filter_ref_deltas: fh.loop_filter_params.loop_filter_ref_deltas,
+        loop_filter_mode_deltas: fh.loop_filter_params.loop_filter_mode_deltas,
+        ..Default::default()
+    };
 
     if fh.coded_lossless || fh.allow_intrabc {
         lfp.loop_filter_level[0] = 0;
@@ -763,7 +1150,7 @@ fn parse_loop_filter_params<R: io::Read>(
         for i in 0..2 {
             lfp.loop_filter_mode_deltas[i] = 0;
         }
-        return Some(lfp);
+        return Ok(lfp);
     }
     lfp.loop_filter_level[0] = br.f::<u8>(6)?; // f(6)
     lfp.loop_filter_level[1] = br.f::<u8>(6)?; // f(6)
@@ -776,8 +1163,8 @@ fn parse_loop_filter_params<R: io::Read>(
     lfp.loop_filter_sharpness = br.f::<u8>(3)?; // f(3)
     lfp.loop_filter_delta_enabled = br.f::<bool>(1)?; // f(1)
     if lfp.loop_filter_delta_enabled {
-        let loop_filter_delta_update = br.f::<bool>(1)?; // f(1)
-        if loop_filter_delta_update {
+        lfp.loop_filter_delta_update = br.f::<bool>(1)?; // f(1)
+        if lfp.loop_filter_delta_update {
             for i in 0..TOTAL_REFS_PER_FRAME {
                 let update_ref_delta = br.f::<bool>(1)?; // f(1)
                 if update_ref_delta {
@@ -793,17 +1180,17 @@ fn parse_loop_filter_params<R: io::Read>(
         }
     }
 
-    Some(lfp)
+    Ok(lfp)
 }
 
 ///
 /// parse tile_info()
 ///
-fn parse_tile_info<R: io::Read>(
-    br: &mut BitReader<R>,
+fn parse_tile_info(
+    br: &mut BitReader,
     sh: &SequenceHeader,
     fs: &FrameSize,
-) -> Option<TileInfo> {
+) -> Result<TileInfo, ParseError> {
     let mut ti = TileInfo::default();
 
     // tile_log2: Tile size calculation function
@@ -915,6 +1302,8 @@ fn parse_tile_info<R: io::Read>(
         ti.tile_rows = i;
         tile_rows_log2 = tile_log2(1, ti.tile_rows as u32);
     }
+    ti.tile_cols_log2 = tile_cols_log2;
+    ti.tile_rows_log2 = tile_rows_log2;
     if tile_cols_log2 > 0 || tile_rows_log2 > 0 {
         ti.context_update_tile_id = br.f::<u32>(tile_cols_log2 + tile_rows_log2)?; // f(TileRowsLog2+TileColsLog2)
         ti.tile_size_bytes = br.f::<usize>(2)? + 1; // f(2)
@@ -922,16 +1311,16 @@ fn parse_tile_info<R: io::Read>(
         ti.context_update_tile_id = 0;
     }
 
-    Some(ti)
+    Ok(ti)
 }
 
 ///
 /// parse quantization_params()
 ///
-fn parse_quantization_params<R: io::Read>(
-    br: &mut BitReader<R>,
+fn parse_quantization_params(
+    br: &mut BitReader,
     cc: &ColorConfig,
-) -> Option<QuantizationParams> {
+) -> Result<QuantizationParams, ParseError> {
     let mut qp = QuantizationParams::default();
 
     qp.base_q_idx = br.f::<u8>(8)?; // f(8)
@@ -969,11 +1358,11 @@ fn parse_quantization_params<R: io::Read>(
         }
     }
 
-    Some(qp)
+    Ok(qp)
 }
 
 /// Delta quantizer
-fn read_delta_q<R: io::Read>(br: &mut BitReader<R>) -> Option<i32> {
+fn read_delta_q(br: &mut BitReader) -> Result<i32, ParseError> {
     let delta_coded = br.f::<bool>(1)?; // f(1)
     let delta_q;
     if delta_coded {
@@ -982,17 +1371,22 @@ fn read_delta_q<R: io::Read>(br: &mut BitReader<R>) -> Option<i32> {
         delta_q = 0;
     }
 
-    Some(delta_q as i32)
+    Ok(delta_q as i32)
 }
 
 ///
 /// parse segmentation_params()
 ///
-fn parse_segmentation_params<R: io::Read>(
-    br: &mut BitReader<R>,
+fn parse_segmentation_params(
+    br: &mut BitReader,
     fh: &FrameHeader,
-) -> Option<SegmentationParams> {
-    let mut sp = SegmentationParams::default();
+) -> Result<SegmentationParams, ParseError> {
+    // FeatureEnabled/FeatureData are inherited from setup_past_independence() or load_previous()
+    let mut sp = SegmentationParams {
+        feature_enabled: fh.segmentation_params.feature_enabled,
+        feature_data: fh.segmentation_params.feature_data,
+        ..Default::default()
+    };
 
     #[allow(non_upper_case_globals)]
     const Segmentation_Feature_Bits: [usize; SEG_LVL_MAX] = [8, 6, 6, 6, 6, 3, 0, 0];
@@ -1024,12 +1418,11 @@ fn parse_segmentation_params<R: io::Read>(
             sp.segmentation_update_data = br.f::<bool>(1)?; // f(1)
         }
         if sp.segmentation_update_data {
-            for _ in 0..MAX_SEGMENTS {
+            for i in 0..MAX_SEGMENTS {
                 for j in 0..SEG_LVL_MAX {
                     let feature_value;
                     let feature_enabled = br.f::<bool>(1)?; // f(1)
-
-                    // FeatureEnabled[i][j] = feature_enabled
+                    sp.feature_enabled[i][j] = feature_enabled;
                     let mut clipped_value = 0;
                     if feature_enabled {
                         let bits_to_read = Segmentation_Feature_Bits[j];
@@ -1042,27 +1435,37 @@ fn parse_segmentation_params<R: io::Read>(
                             clipped_value = cmp::max(0, cmp::min(limit, feature_value));
                         }
                     }
-                    let _ = clipped_value; // FeatureData[i][j] = clippedValue
+                    sp.feature_data[i][j] = clipped_value;
                 }
             }
         }
     } else {
-        // FeatureEnabled[i][j] = 0
-        // FeatureData[i][j] = 0
+        sp.feature_enabled = [[false; SEG_LVL_MAX]; MAX_SEGMENTS];
+        sp.feature_data = [[0; SEG_LVL_MAX]; MAX_SEGMENTS];
+    }
+    sp.seg_id_pre_skip = false;
+    sp.last_active_seg_id = 0;
+    for i in 0..MAX_SEGMENTS {
+        for j in 0..SEG_LVL_MAX {
+            if sp.feature_enabled[i][j] {
+                sp.last_active_seg_id = i as u8;
+                if j >= SEG_LVL_REF_FRAME {
+                    sp.seg_id_pre_skip = true;
+                }
+            }
+        }
     }
-    // SegIdPreSkip
-    // LastActiveSegId
 
-    Some(sp)
+    Ok(sp)
 }
 
 ///
 /// parse delta_q_params()
 ///
-fn parse_delta_q_params<R: io::Read>(
-    br: &mut BitReader<R>,
+fn parse_delta_q_params(
+    br: &mut BitReader,
     qp: &QuantizationParams,
-) -> Option<DeltaQParams> {
+) -> Result<DeltaQParams, ParseError> {
     let mut dqp = DeltaQParams::default();
 
     dqp.delta_q_res = 0;
@@ -1074,16 +1477,16 @@ fn parse_delta_q_params<R: io::Read>(
         dqp.delta_q_res = br.f::<u8>(2)?; // f(2)
     }
 
-    Some(dqp)
+    Ok(dqp)
 }
 
 ///
 /// parse delta_lf_params()
 ///
-fn parse_delta_lf_params<R: io::Read>(
-    br: &mut BitReader<R>,
+fn parse_delta_lf_params(
+    br: &mut BitReader,
     fh: &FrameHeader,
-) -> Option<DeltaLfParams> {
+) -> Result<DeltaLfParams, ParseError> {
     let mut dlfp = DeltaLfParams::default();
 
     dlfp.delta_lf_present = false;
@@ -1099,17 +1502,17 @@ fn parse_delta_lf_params<R: io::Read>(
         }
     }
 
-    Some(dlfp)
+    Ok(dlfp)
 }
 
 ///
 /// parse cdef_params()
 ///
-fn parse_cdef_params<R: io::Read>(
-    br: &mut BitReader<R>,
+fn parse_cdef_params(
+    br: &mut BitReader,
     sh: &SequenceHeader,
     fh: &FrameHeader,
-) -> Option<CdefParams> {
+) -> Result<CdefParams, ParseError> {
     let mut cdefp = CdefParams::default();
 
     if fh.coded_lossless || fh.allow_intrabc || !sh.enable_cdef {
@@ -1119,7 +1522,7 @@ fn parse_cdef_params<R: io::Read>(
         cdefp.cdef_uv_pri_strength[0] = 0;
         cdefp.cdef_uv_sec_strength[0] = 0;
         cdefp.cdef_damping = 3;
-        return Some(cdefp);
+        return Ok(cdefp);
     }
     cdefp.cdef_damping = br.f::<u8>(2)? + 3; // f(2)
     cdefp.cdef_bits = br.f::<u8>(2)?; // f(2)
@@ -1138,17 +1541,17 @@ fn parse_cdef_params<R: io::Read>(
         }
     }
 
-    Some(cdefp)
+    Ok(cdefp)
 }
 
 ///
 /// parse lr_params()
 ///
-fn parse_lr_params<R: io::Read>(
-    br: &mut BitReader<R>,
+fn parse_lr_params(
+    br: &mut BitReader,
     sh: &SequenceHeader,
     fh: &FrameHeader,
-) -> Option<LrParams> {
+) -> Result<LrParams, ParseError> {
     let mut lrp = LrParams::default();
 
     #[allow(non_upper_case_globals)]
@@ -1164,7 +1567,7 @@ fn parse_lr_params<R: io::Read>(
         lrp.frame_restoration_type[1] = RESTORE_NONE;
         lrp.frame_restoration_type[2] = RESTORE_NONE;
         lrp.uses_lr = false;
-        return Some(lrp);
+        return Ok(lrp);
     }
     lrp.uses_lr = false;
     let mut use_chroma_lr = false;
@@ -1202,11 +1605,11 @@ fn parse_lr_params<R: io::Read>(
         lrp.loop_restoration_size[2] = lrp.loop_restoration_size[0] >> lr_uv_shift;
     }
 
-    Some(lrp)
+    Ok(lrp)
 }
 
 /// read_tx_mode()
-fn read_tx_mode<R: io::Read>(br: &mut BitReader<R>, fh: &FrameHeader) -> Option<u8> {
+fn read_tx_mode(br: &mut BitReader, fh: &FrameHeader) -> Result<u8, ParseError> {
     let tx_mode: u8;
     if fh.coded_lossless {
         tx_mode = ONLY_4X4;
@@ -1219,18 +1622,18 @@ fn read_tx_mode<R: io::Read>(br: &mut BitReader<R>, fh: &FrameHeader) -> Option<
         }
     }
 
-    Some(tx_mode)
+    Ok(tx_mode)
 }
 
 ///
 /// parse skip_mode_params()
 ///
-fn parse_skip_mode_params<R: io::Read>(
-    br: &mut BitReader<R>,
+fn parse_skip_mode_params(
+    br: &mut BitReader,
     sh: &SequenceHeader,
     fh: &FrameHeader,
     rfman: &av1::RefFrameManager,
-) -> Option<SkipModeParams> {
+) -> Result<SkipModeParams, ParseError> {
     let mut smp = SkipModeParams::default();
 
     let skip_mode_allowed;
@@ -1293,16 +1696,16 @@ fn parse_skip_mode_params<R: io::Read>(
         smp.skip_mode_present = false;
     }
 
-    Some(smp)
+    Ok(smp)
 }
 
 ///
 /// parse global_motion_params()
 ///
-fn parse_global_motion_params<R: io::Read>(
-    br: &mut BitReader<R>,
+fn parse_global_motion_params(
+    br: &mut BitReader,
     fh: &FrameHeader,
-) -> Option<GlobalMotionParams> {
+) -> Result<GlobalMotionParams, ParseError> {
     let mut gmp = GlobalMotionParams::default();
 
     for ref_ in LAST_FRAME..=ALTREF_FRAME {
@@ -1316,7 +1719,7 @@ fn parse_global_motion_params<R: io::Read>(
         }
     }
     if fh.frame_is_intra {
-        return Some(gmp);
+        return Ok(gmp);
     }
     for ref_ in LAST_FRAME..=ALTREF_FRAME {
         let is_global = br.f::<bool>(1)?; // f(1)
@@ -1351,17 +1754,17 @@ fn parse_global_motion_params<R: io::Read>(
         }
     }
 
-    Some(gmp)
+    Ok(gmp)
 }
 
 /// read_global_param() return gm_params[ref][idx]
-fn read_global_param<R: io::Read>(
-    br: &mut BitReader<R>,
+fn read_global_param(
+    br: &mut BitReader,
     type_: u8,
     ref_: usize,
     idx: usize,
     fh: &FrameHeader,
-) -> Option<i32> {
+) -> Result<i32, ParseError> {
     let mut abs_bits = GM_ABS_ALPHA_BITS;
     let mut prec_bits = GM_ALPHA_PREC_BITS;
     if idx < 2 {
@@ -1384,36 +1787,32 @@ fn read_global_param<R: io::Read>(
     let r = (fh.global_motion_params.prev_gm_params[ref_][idx] >> prec_diff) - sub;
     let gm_params = (decode_signed_subexp_with_ref(br, -mx, mx + 1, r)? << prec_diff) + round;
 
-    Some(gm_params)
+    Ok(gm_params)
 }
 
 /// decode_signed_subexp_with_ref()
-fn decode_signed_subexp_with_ref<R: io::Read>(
-    br: &mut BitReader<R>,
+fn decode_signed_subexp_with_ref(
+    br: &mut BitReader,
     low: i32,
     high: i32,
     r: i32,
-) -> Option<i32> {
+) -> Result<i32, ParseError> {
     let x = decode_unsigned_subexp_with_ref(br, high - low, r - low)?;
-    Some(x + low)
+    Ok(x + low)
 }
 
 /// decode_unsigned_subexp_with_ref()
-fn decode_unsigned_subexp_with_ref<R: io::Read>(
-    br: &mut BitReader<R>,
-    mx: i32,
-    r: i32,
-) -> Option<i32> {
+fn decode_unsigned_subexp_with_ref(br: &mut BitReader, mx: i32, r: i32) -> Result<i32, ParseError> {
     let v = decode_subexp(br, mx)?;
     if (r << 1) <= mx {
-        Some(inverse_recenter(r, v))
+        Ok(inverse_recenter(r, v))
     } else {
-        Some(mx - 1 - inverse_recenter(mx - 1 - r, v))
+        Ok(mx - 1 - inverse_recenter(mx - 1 - r, v))
     }
 }
 
 /// decode_subexp()
-fn decode_subexp<R: io::Read>(br: &mut BitReader<R>, num_syms: i32) -> Option<i32> {
+fn decode_subexp(br: &mut BitReader, num_syms: i32) -> Result<i32, ParseError> {
     let mut i = 0;
     let mut mk = 0;
     let k = 3;
@@ -1422,7 +1821,7 @@ fn decode_subexp<R: io::Read>(br: &mut BitReader<R>, num_syms: i32) -> Option<i3
         let a = 1 << b2;
         if num_syms <= mk + 3 * a {
             let subexp_final_bits = br.ns((num_syms - mk) as u32)? as i32; // ns(numSyms-mk)
-            return Some(subexp_final_bits + mk);
+            return Ok(subexp_final_bits + mk);
         } else {
             let subexp_more_bits = br.f::<bool>(1)?; // f(1)
             if subexp_more_bits {
@@ -1430,7 +1829,7 @@ fn decode_subexp<R: io::Read>(br: &mut BitReader<R>, num_syms: i32) -> Option<i3
                 mk += a;
             } else {
                 let subexp_bits = br.ns(b2)? as i32; // ns(b2)
-                return Some(subexp_bits + mk);
+                return Ok(subexp_bits + mk);
             }
         }
     }
@@ -1451,22 +1850,23 @@ fn inverse_recenter(r: i32, v: i32) -> i32 {
 ///
 /// parse film_grain_params()
 ///
-fn parse_film_grain_params<R: io::Read>(
-    br: &mut BitReader<R>,
+fn parse_film_grain_params(
+    br: &mut BitReader,
     sh: &SequenceHeader,
     fh: &FrameHeader,
-) -> Option<FilmGrainParams> {
+    rfman: &av1::RefFrameManager,
+) -> Result<FilmGrainParams, ParseError> {
     let mut fgp = FilmGrainParams::default();
 
-    if !sh.film_grain_params_present || (!fh.show_frame && fh.showable_frame) {
+    if !sh.film_grain_params_present || (!fh.show_frame && !fh.showable_frame) {
         // reset_grain_params()
-        return Some(fgp);
+        return Ok(fgp);
     }
 
     fgp.apply_grain = br.f::<bool>(1)?; // f(1)
     if !fgp.apply_grain {
         // reset_grain_params()
-        return Some(fgp);
+        return Ok(fgp);
     }
 
     fgp.grain_seed = br.f::<u16>(16)?; // f(16)
@@ -1478,14 +1878,31 @@ fn parse_film_grain_params<R: io::Read>(
     };
 
     if !fgp.update_grain {
-        fgp.film_grain_params_ref_idx = br.f::<u8>(3)?;
+        let film_grain_params_ref_idx = br.f::<u8>(3)?; // f(3)
+
+        // It is a requirement of bitstream conformance that film_grain_params_ref_idx is equal to
+        // ref_frame_idx[j] for some value of j in the range 0 to REFS_PER_FRAME - 1.
+        if !fh.ref_frame_idx[0..REFS_PER_FRAME].contains(&film_grain_params_ref_idx) {
+            return Err(br.error(ParseErrorKind::Conformance("film_grain_params_ref_idx")));
+        }
 
-        assert!(fgp.film_grain_params_ref_idx <= (REFS_PER_FRAME - 1) as u8);
+        let temp_grain_seed = fgp.grain_seed;
+        fgp = rfman.load_grain_params(film_grain_params_ref_idx as usize); // load_grain_params()
+        fgp.grain_seed = temp_grain_seed;
+        // keep the signaled values to tell inherited parameters
+        fgp.update_grain = false;
+        fgp.film_grain_params_ref_idx = film_grain_params_ref_idx;
+        return Ok(fgp);
     }
 
     fgp.num_y_points = br.f::<u8>(4)?;
 
-    assert!(fgp.num_y_points <= 14);
+    if fgp.num_y_points > 14 {
+        return Err(br.error(ParseErrorKind::InvalidValue {
+            element: "num_y_points",
+            value: fgp.num_y_points as i64,
+        }));
+    }
 
     for _ in 0..fgp.num_y_points {
         fgp.point_y_value.push(br.f::<u8>(8)?); // f(8)
@@ -1521,8 +1938,18 @@ fn parse_film_grain_params<R: io::Read>(
         }
     }
 
-    assert!(fgp.num_cb_points <= 10);
-    assert!(fgp.num_cr_points <= 10);
+    if fgp.num_cb_points > 10 {
+        return Err(br.error(ParseErrorKind::InvalidValue {
+            element: "num_cb_points",
+            value: fgp.num_cb_points as i64,
+        }));
+    }
+    if fgp.num_cr_points > 10 {
+        return Err(br.error(ParseErrorKind::InvalidValue {
+            element: "num_cr_points",
+            value: fgp.num_cr_points as i64,
+        }));
+    }
 
     fgp.grain_scaling_minus_8 = br.f::<u8>(2)?; // f(2)
     fgp.ar_coeff_lag = br.f::<u8>(2)?; // f(2)
@@ -1569,12 +1996,13 @@ fn parse_film_grain_params<R: io::Read>(
     fgp.overlap_flag = br.f::<bool>(1)?; // f(1)
     fgp.clip_to_restricted_range = br.f::<bool>(1)?; // f(1)
 
-    Some(fgp)
+    Ok(fgp)
 }
 
 /// setup_past_independence()
 fn setup_past_independence(fh: &mut FrameHeader) {
-    // FeatureData[i][j]
+    fh.segmentation_params.feature_data = [[0; SEG_LVL_MAX]; MAX_SEGMENTS];
+    fh.segmentation_params.feature_enabled = [[false; SEG_LVL_MAX]; MAX_SEGMENTS];
     // PrevSegmentIds[row][col]
     for ref_ in LAST_FRAME..=ALTREF_FRAME {
         fh.global_motion_params.gm_type[ref_] = IDENTITY;
@@ -1603,58 +2031,181 @@ fn setup_past_independence(fh: &mut FrameHeader) {
 fn load_previous(fh: &mut FrameHeader, rfman: &av1::RefFrameManager) {
     let prev_frame = fh.ref_frame_idx[fh.primary_ref_frame as usize] as usize;
     fh.global_motion_params.prev_gm_params = rfman.saved_gm_params[prev_frame];
+    // load_loop_filter_params(prevFrame)
+    fh.loop_filter_params.loop_filter_ref_deltas = rfman.saved_loop_filter_ref_deltas[prev_frame];
+    fh.loop_filter_params.loop_filter_mode_deltas = rfman.saved_loop_filter_mode_deltas[prev_frame];
+    // load_segmentation_params(prevFrame)
+    fh.segmentation_params.feature_enabled = rfman.saved_feature_enabled[prev_frame];
+    fh.segmentation_params.feature_data = rfman.saved_feature_data[prev_frame];
+}
+
+/// get_qindex(): Get quantizer index process
+fn get_qindex(fh: &FrameHeader, ignore_delta_q: bool, segment_id: usize) -> u8 {
+    let sp = &fh.segmentation_params;
+    let base_q_idx = fh.quantization_params.base_q_idx as i32;
+    // CurrentQIndex is equal to base_q_idx at the start of each tile
+    let current_q_index = base_q_idx;
+    // seg_feature_active_idx(segmentId, SEG_LVL_ALT_Q)
+    if sp.segmentation_enabled && sp.feature_enabled[segment_id][SEG_LVL_ALT_Q] {
+        let data = sp.feature_data[segment_id][SEG_LVL_ALT_Q];
+        let mut qindex = base_q_idx + data;
+        if !ignore_delta_q && fh.delta_q_params.delta_q_present {
+            qindex = current_q_index + data;
+        }
+        qindex.clamp(0, 255) as u8
+    } else if !ignore_delta_q && fh.delta_q_params.delta_q_present {
+        current_q_index as u8
+    } else {
+        base_q_idx as u8
+    }
+}
+
+/// set_frame_refs(): Set frame refs process
+fn set_frame_refs(fh: &mut FrameHeader, sh: &SequenceHeader, rfman: &av1::RefFrameManager) {
+    let mut ref_frame_idx = [-1; REFS_PER_FRAME];
+    ref_frame_idx[0] = fh.last_frame_idx as i32; // LAST_FRAME - LAST_FRAME
+    ref_frame_idx[GOLDEN_FRAME - LAST_FRAME] = fh.gold_frame_idx as i32;
+    let mut used_frame = [false; NUM_REF_FRAMES];
+    used_frame[fh.last_frame_idx as usize] = true;
+    used_frame[fh.gold_frame_idx as usize] = true;
+
+    let cur_frame_hint = 1 << (sh.order_hint_bits - 1);
+    let mut shifted_order_hints = [0; NUM_REF_FRAMES];
+    for (i, hint) in shifted_order_hints.iter_mut().enumerate() {
+        *hint = cur_frame_hint
+            + av1::get_relative_dist(rfman.ref_order_hint[i] as i32, fh.order_hint as i32, sh);
+    }
+
+    // lastOrderHint and goldOrderHint specify the shifted order hints of LAST_FRAME and GOLDEN_FRAME.
+    // It is a requirement of bitstream conformance that lastOrderHint and goldOrderHint are
+    // strictly less than curFrameHint.
+
+    // find_latest_backward()
+    let find_latest_backward = |used_frame: &[bool; NUM_REF_FRAMES]| {
+        let (mut ref_, mut latest_order_hint) = (-1, 0);
+        for (i, &hint) in shifted_order_hints.iter().enumerate() {
+            if !used_frame[i] && hint >= cur_frame_hint && (ref_ < 0 || hint >= latest_order_hint) {
+                ref_ = i as i32;
+                latest_order_hint = hint;
+            }
+        }
+        ref_
+    };
+    // find_earliest_backward()
+    let find_earliest_backward = |used_frame: &[bool; NUM_REF_FRAMES]| {
+        let (mut ref_, mut earliest_order_hint) = (-1, 0);
+        for (i, &hint) in shifted_order_hints.iter().enumerate() {
+            if !used_frame[i] && hint >= cur_frame_hint && (ref_ < 0 || hint < earliest_order_hint)
+            {
+                ref_ = i as i32;
+                earliest_order_hint = hint;
+            }
+        }
+        ref_
+    };
+    // find_latest_forward()
+    let find_latest_forward = |used_frame: &[bool; NUM_REF_FRAMES]| {
+        let (mut ref_, mut latest_order_hint) = (-1, 0);
+        for (i, &hint) in shifted_order_hints.iter().enumerate() {
+            if !used_frame[i] && hint < cur_frame_hint && (ref_ < 0 || hint >= latest_order_hint) {
+                ref_ = i as i32;
+                latest_order_hint = hint;
+            }
+        }
+        ref_
+    };
+
+    let ref_ = find_latest_backward(&used_frame);
+    if ref_ >= 0 {
+        ref_frame_idx[ALTREF_FRAME - LAST_FRAME] = ref_;
+        used_frame[ref_ as usize] = true;
+    }
+    let ref_ = find_earliest_backward(&used_frame);
+    if ref_ >= 0 {
+        ref_frame_idx[BWDREF_FRAME - LAST_FRAME] = ref_;
+        used_frame[ref_ as usize] = true;
+    }
+    let ref_ = find_earliest_backward(&used_frame);
+    if ref_ >= 0 {
+        ref_frame_idx[ALTREF2_FRAME - LAST_FRAME] = ref_;
+        used_frame[ref_ as usize] = true;
+    }
+
+    #[allow(non_upper_case_globals)]
+    const Ref_Frame_List: [usize; REFS_PER_FRAME - 2] = [
+        LAST2_FRAME,
+        LAST3_FRAME,
+        BWDREF_FRAME,
+        ALTREF2_FRAME,
+        ALTREF_FRAME,
+    ];
+    for ref_frame in Ref_Frame_List.iter() {
+        if ref_frame_idx[ref_frame - LAST_FRAME] < 0 {
+            let ref_ = find_latest_forward(&used_frame);
+            if ref_ >= 0 {
+                ref_frame_idx[ref_frame - LAST_FRAME] = ref_;
+                used_frame[ref_ as usize] = true;
+            }
+        }
+    }
+
+    // Finally, any remaining references are set to the reference frame with smallest output order
+    let (mut ref_, mut earliest_order_hint) = (-1, 0);
+    for (i, &hint) in shifted_order_hints.iter().enumerate() {
+        if ref_ < 0 || hint < earliest_order_hint {
+            ref_ = i as i32;
+            earliest_order_hint = hint;
+        }
+    }
+    for (i, idx) in ref_frame_idx.iter_mut().enumerate() {
+        if *idx < 0 {
+            *idx = ref_;
+        }
+        fh.ref_frame_idx[i] = *idx as u8;
+    }
 }
 
 ///
-/// parse AV1 OBU header
+/// parse AV1 OBU header on byte buffer
 ///
-pub fn parse_obu_header<R: io::Read>(bs: &mut R, sz: u32) -> io::Result<Obu> {
+fn read_obu_header(br: &mut BitReader, sz: u32) -> Result<Obu, ParseError> {
     // parse obu_header()
-    let mut b1 = [0; 1];
-    bs.read_exact(&mut b1)?;
-    let obu_forbidden_bit = (b1[0] >> 7) & 1; // f(1)
+    let obu_forbidden_bit = br.f::<u8>(1)?; // f(1)
     if obu_forbidden_bit != 0 {
-        return Err(io::Error::new(
-            io::ErrorKind::InvalidData,
-            "obu_forbidden_bit!=0",
-        ));
+        return Err(br.error(ParseErrorKind::Conformance("obu_forbidden_bit")));
     }
-    let obu_type = (b1[0] >> 3) & 0b1111; // f(4)
-    let obu_extension_flag = (b1[0] >> 2) & 1; // f(1)
-    let obu_has_size_field = (b1[0] >> 1) & 1; // f(1)
-    let (temporal_id, spatial_id) = if obu_extension_flag == 1 {
+    let obu_type = br.f::<u8>(4)?; // f(4)
+    let obu_extension_flag = br.f::<bool>(1)?; // f(1)
+    let obu_has_size_field = br.f::<bool>(1)?; // f(1)
+    let _obu_reserved_1bit = br.f::<u8>(1)?; // f(1)
+    let (temporal_id, spatial_id) = if obu_extension_flag {
         // parse obu_extension_header()
-        let mut b2 = [0; 1];
-        bs.read_exact(&mut b2)?;
-        ((b2[0] >> 5) & 0b111, (b2[0] >> 3) & 0b11) // f(3),f(2)
+        let temporal_id = br.f::<u8>(3)?; // f(3)
+        let spatial_id = br.f::<u8>(2)?; // f(2)
+        let _extension_header_reserved_3bits = br.f::<u8>(3)?; // f(3)
+        (temporal_id, spatial_id)
     } else {
         (0, 0)
     };
     // parse 'obu_size' in open_bitstream_unit()
     let obu_header_len = 1 + (obu_extension_flag as u32);
-    let (obu_size_len, obu_size) = if obu_has_size_field == 1 {
-        leb128(bs)?
+    let (obu_size_len, obu_size) = if obu_has_size_field {
+        br.leb128()? // leb128()
     } else {
         if sz < obu_header_len {
-            return Err(io::Error::new(
-                io::ErrorKind::InvalidData,
-                "invalid sz in open_bitstream_unit()",
-            ));
+            return Err(br.error(ParseErrorKind::Truncated));
         }
         (0, sz - obu_header_len)
     };
 
-    if sz < obu_header_len + obu_size_len + obu_size {
-        return Err(io::Error::new(
-            io::ErrorKind::InvalidData,
-            "invalid OBU size",
-        ));
+    if (sz as u64) < (obu_header_len + obu_size_len) as u64 + obu_size as u64 {
+        return Err(br.error(ParseErrorKind::Truncated));
     }
 
     Ok(Obu {
         obu_type,
-        obu_extension_flag: obu_extension_flag == 1,
-        obu_has_size_field: obu_has_size_field == 1,
+        obu_extension_flag,
+        obu_has_size_field,
         temporal_id,
         spatial_id,
         obu_size,
@@ -1662,14 +2213,43 @@ pub fn parse_obu_header<R: io::Read>(bs: &mut R, sz: u32) -> io::Result<Obu> {
     })
 }
 
+///
+/// parse AV1 OBU header
+///
+/// Read obu_header() and obu_size bytes from `bs`, then parse them with `read_obu_header`.
+///
+pub fn parse_obu_header<R: io::Read>(bs: &mut R, sz: u32) -> io::Result<Obu> {
+    let mut buf = [0; 10]; // obu_header(), obu_extension_header() and leb128() obu_size
+    bs.read_exact(&mut buf[..1])?;
+    let obu_extension_flag = (buf[0] >> 2) & 1; // f(1)
+    let obu_has_size_field = (buf[0] >> 1) & 1; // f(1)
+    let mut len = 1 + obu_extension_flag as usize;
+    bs.read_exact(&mut buf[1..len])?;
+    if obu_has_size_field == 1 {
+        for _ in 0..8 {
+            bs.read_exact(&mut buf[len..len + 1])?;
+            len += 1;
+            if (buf[len - 1] & 0x80) != 0x80 {
+                break;
+            }
+        }
+    }
+    read_obu_header(&mut BitReader::new(&buf[..len]), sz)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
 ///
 /// parse sequence_header_obu()
 ///
-pub fn parse_sequence_header<R: io::Read>(bs: &mut R) -> Option<SequenceHeader> {
-    let mut br = BitReader::new(bs);
+pub fn parse_sequence_header(data: &[u8]) -> Result<SequenceHeader, ParseError> {
+    let mut br = BitReader::new(data);
     let mut sh = SequenceHeader::default();
 
     sh.seq_profile = br.f::<u8>(3)?; // f(3)
+    if sh.seq_profile > 2 {
+        // reserved seq_profile has unknown color_config() syntax
+        return Err(br.error(ParseErrorKind::Unsupported("seq_profile > 2")));
+    }
     sh.still_picture = br.f::<bool>(1)?; // f(1)
     sh.reduced_still_picture_header = br.f::<bool>(1)?; // f(1)
     sh.op.push(Default::default());
@@ -1692,12 +2272,12 @@ pub fn parse_sequence_header<R: io::Read>(bs: &mut R) -> Option<SequenceHeader>
             if sh.decoder_model_info_present_flag {
                 let buffer_delay_length = br.f::<u8>(5)? + 1;
                 let num_units_in_decoding_tick = br.f::<u32>(32)?;
-                let buffer_removal_time = br.f::<u8>(5)? + 1;
+                let buffer_removal_time_length = br.f::<u8>(5)? + 1;
                 let frame_presentation_time_length = br.f::<u8>(5)? + 1;
                 sh.decoder_model_info = Some(DecoderModelInfo {
                     buffer_delay_length,
                     num_units_in_decoding_tick,
-                    buffer_removal_time,
+                    buffer_removal_time_length,
                     frame_presentation_time_length,
                 });
             }
@@ -1717,21 +2297,22 @@ pub fn parse_sequence_header<R: io::Read>(bs: &mut R) -> Option<SequenceHeader>
                 sh.op[i].seq_tier = 0;
             }
             if let Some(ref decoder_model_info) = sh.decoder_model_info {
-                if br.f::<bool>(1)? {
-                    let n = decoder_model_info.buffer_delay_length;
-                    let _decoder_delay = br.f::<u32>(n as usize);
-                    let _encoder_delay = br.f::<u32>(n as usize);
-                    let _low_delay_mode = br.f::<bool>(1);
+                sh.op[i].decoder_model_present_for_this_op = br.f::<bool>(1)?; // f(1)
+                if sh.op[i].decoder_model_present_for_this_op {
+                    // operating_parameters_info(i)
+                    sh.op[i].operating_parameters_info =
+                        parse_operating_parameters_info(&mut br, decoder_model_info)?;
                 }
             }
             if sh.initial_display_delay_present_flag {
-                if br.f::<bool>(1)? {
-                    let _delay = br.f::<u8>(4)?;
+                sh.op[i].initial_display_delay_present_for_this_op = br.f::<bool>(1)?; // f(1)
+                if sh.op[i].initial_display_delay_present_for_this_op {
+                    sh.op[i].initial_display_delay_minus_1 = br.f::<u8>(4)?; // f(4)
                 }
             }
         }
     }
-    // operatingPoint = choose_operating_point()
+    // operatingPoint = choose_operating_point() (see av1::Sequence)
     // OperatingPointIdc = operating_point_idc[operatingPoint]
     sh.frame_width_bits = br.f::<u8>(4)? + 1; // f(4)
     sh.frame_height_bits = br.f::<u8>(4)? + 1; // f(4)
@@ -1802,18 +2383,19 @@ pub fn parse_sequence_header<R: io::Read>(bs: &mut R) -> Option<SequenceHeader>
     sh.film_grain_params_present = br.f::<bool>(1)?; // f(1)
     trailing_bits(&mut br)?;
 
-    Some(sh)
+    Ok(sh)
 }
 
 ///
-/// parse frame_header
+/// parse frame_header, return (frame header, uncompressed_header() size in bits)
 ///
-pub fn parse_frame_header<R: io::Read>(
-    bs: &mut R,
+pub fn parse_frame_header(
+    data: &[u8],
+    obu: &Obu,
     sh: &SequenceHeader,
     rfman: &mut av1::RefFrameManager,
-) -> Option<FrameHeader> {
-    let mut br = BitReader::new(bs);
+) -> Result<(FrameHeader, u64), ParseError> {
+    let mut br = BitReader::new(data);
     let mut fh = FrameHeader::default();
 
     // uncompressed_header()
@@ -1822,7 +2404,9 @@ pub fn parse_frame_header<R: io::Read>(
     } else {
         0
     } as usize;
-    assert!(id_len <= 16);
+    if id_len > 16 {
+        return Err(br.error(ParseErrorKind::Conformance("idLen <= 16")));
+    }
     assert!(NUM_REF_FRAMES <= 8);
     let all_frames = ((1usize << NUM_REF_FRAMES) - 1) as u8; // 0xff
     if sh.reduced_still_picture_header {
@@ -1836,10 +2420,8 @@ pub fn parse_frame_header<R: io::Read>(
         if fh.show_existing_frame {
             fh.frame_to_show_map_idx = br.f::<u8>(3)?; // f(3)
             if sh.decoder_model_info_present_flag && !sh.timing_info.equal_picture_interval {
-                if let Some(ref decoder_model_info) = sh.decoder_model_info {
-                    let _frame_presentation_time =
-                        br.f::<u32>(decoder_model_info.frame_presentation_time_length as usize)?;
-                }
+                // temporal_point_info()
+                fh.frame_presentation_time = parse_temporal_point_info(&mut br, sh)?;
             }
             fh.refresh_frame_flags = 0;
             if sh.frame_id_numbers_present_flag {
@@ -1848,11 +2430,14 @@ pub fn parse_frame_header<R: io::Read>(
             fh.frame_type = rfman.ref_frame_type[fh.frame_to_show_map_idx as usize];
             if fh.frame_type == KEY_FRAME {
                 fh.refresh_frame_flags = all_frames;
+                // Reference frame loading process
+                rfman.load_process(&mut fh);
             }
             if sh.film_grain_params_present {
-                unimplemented!("load_grain_params()");
+                // load_grain_params(frame_to_show_map_idx)
+                fh.film_grain_params = rfman.load_grain_params(fh.frame_to_show_map_idx as usize);
             }
-            return Some(fh);
+            return Ok((fh, br.position()));
         }
         fh.frame_type = br.f::<u8>(2)?; // f(2)
         fh.frame_is_intra = fh.frame_type == INTRA_ONLY_FRAME || fh.frame_type == KEY_FRAME;
@@ -1861,10 +2446,8 @@ pub fn parse_frame_header<R: io::Read>(
             && sh.decoder_model_info_present_flag
             && !sh.timing_info.equal_picture_interval
         {
-            if let Some(ref decoder_model_info) = sh.decoder_model_info {
-                let _frame_presentation_time =
-                    br.f::<u32>(decoder_model_info.frame_presentation_time_length as usize)?;
-            }
+            // temporal_point_info()
+            fh.frame_presentation_time = parse_temporal_point_info(&mut br, sh)?;
         }
         if fh.show_frame {
             fh.showable_frame = fh.frame_type != KEY_FRAME;
@@ -1924,6 +2507,23 @@ pub fn parse_frame_header<R: io::Read>(
     } else {
         fh.primary_ref_frame = br.f::<u8>(3)?; // f(3)
     }
+    if let Some(ref decoder_model_info) = sh.decoder_model_info {
+        fh.buffer_removal_time_present_flag = br.f::<bool>(1)?; // f(1)
+        if fh.buffer_removal_time_present_flag {
+            fh.buffer_removal_time = vec![None; sh.operating_points_cnt as usize];
+            for op_num in 0..(sh.operating_points_cnt as usize) {
+                if sh.op[op_num].decoder_model_present_for_this_op {
+                    let op_pt_idc = sh.op[op_num].operating_point_idc;
+                    let in_temporal_layer = (op_pt_idc >> obu.temporal_id) & 1 != 0;
+                    let in_spatial_layer = (op_pt_idc >> (obu.spatial_id + 8)) & 1 != 0;
+                    if op_pt_idc == 0 || (in_temporal_layer && in_spatial_layer) {
+                        let n = decoder_model_info.buffer_removal_time_length as usize;
+                        fh.buffer_removal_time[op_num] = Some(br.f::<u32>(n)?); // f(n)
+                    }
+                }
+            }
+        }
+    }
     fh.allow_high_precision_mv = false;
     fh.use_ref_frame_mvs = false;
     fh.allow_intrabc = false;
@@ -1968,7 +2568,7 @@ pub fn parse_frame_header<R: io::Read>(
                 if frame_refs_short_signaling {
                     fh.last_frame_idx = br.f::<u8>(3)?; // f(3)
                     fh.gold_frame_idx = br.f::<u8>(3)?; // f(3)
-                    unimplemented!("set_frame_refs()");
+                    set_frame_refs(&mut fh, sh, rfman);
                 }
             }
             for i in 0..REFS_PER_FRAME {
@@ -1979,7 +2579,11 @@ pub fn parse_frame_header<R: io::Read>(
                     // It is a requirement of bitstream conformance that RefValid[ref_frame_idx[i]] is equal to 1,
                     // and that the selected reference frames match the current frame in bit depth, profile,
                     // chroma subsampling, and color space.
-                    assert!(rfman.ref_valid[fh.ref_frame_idx[i] as usize]);
+                    if !rfman.ref_valid[fh.ref_frame_idx[i] as usize] {
+                        return Err(
+                            br.error(ParseErrorKind::Conformance("RefValid[ref_frame_idx[i]]"))
+                        );
+                    }
                 }
                 if sh.frame_id_numbers_present_flag {
                     let delta_frame_id = br.f::<u16>(sh.delta_frame_id_length as usize)? + 1; // f(n)
@@ -1990,14 +2594,15 @@ pub fn parse_frame_header<R: io::Read>(
                     // It is a requirement of bitstream conformance that whenever expectedFrameId[i] is calculated,
                     // the value matches RefFrameId[ref_frame_idx[i]] (this contains the value of current_frame_id
                     // at the time that the frame indexed by ref_frame_idx was stored).
-                    assert_eq!(
-                        expected_frame_id,
-                        rfman.ref_frame_id[fh.ref_frame_idx[i] as usize]
-                    );
+                    if expected_frame_id != rfman.ref_frame_id[fh.ref_frame_idx[i] as usize] {
+                        return Err(br.error(ParseErrorKind::Conformance("expectedFrameId[i]")));
+                    }
                 }
             }
             if fh.frame_size_override_flag && !fh.error_resilient_mode {
-                unimplemented!("frame_size_with_refs()");
+                let (fs, rs) = parse_frame_size_with_refs(&mut br, sh, &fh, rfman)?; // frame_size_with_refs()
+                fh.frame_size = fs;
+                fh.render_size = rs;
             } else {
                 fh.frame_size = parse_frame_size(&mut br, sh, &fh)?; // frame_size()
                 fh.render_size = parse_render_size(&mut br, &fh.frame_size)?; // render_size()
@@ -2021,10 +2626,11 @@ pub fn parse_frame_header<R: io::Read>(
             let ref_frame = LAST_FRAME + i;
             let hint = rfman.ref_order_hint[fh.ref_frame_idx[i] as usize];
             fh.order_hints[ref_frame] = hint;
-            if sh.enable_order_hint {
-                // RefFrameSignBias[refFrame] = 0
+            if !sh.enable_order_hint {
+                fh.ref_frame_sign_bias[ref_frame] = false;
             } else {
-                // RefFrameSignBias[refFrame] = get_relative_dist(hint, OrderHint) > 0
+                fh.ref_frame_sign_bias[ref_frame] =
+                    av1::get_relative_dist(hint as i32, fh.order_hint as i32, sh) > 0;
             }
         }
     }
@@ -2051,12 +2657,31 @@ pub fn parse_frame_header<R: io::Read>(
     if fh.primary_ref_frame == PRIMARY_REF_NONE {
         // init_coeff_cdfs()
     } else {
-        // load_previous_segment_ids()
-    }
-    fh.coded_lossless = false; // FIXME: assume lossy coding
-    for _segment_id in 0..MAX_SEGMENTS {
-        // CodedLossless
-        // SegQMLevel[][segmentId]
+        // load_previous_segment_ids(): PrevSegmentIds needs decoded segment_id map (not supported)
+    }
+    fh.coded_lossless = true;
+    for segment_id in 0..MAX_SEGMENTS {
+        let qindex = get_qindex(&fh, true, segment_id);
+        let qp = &fh.quantization_params;
+        fh.lossless_array[segment_id] = qindex == 0
+            && qp.deltaq_y_dc == 0
+            && qp.deltaq_u_ac == 0
+            && qp.deltaq_u_dc == 0
+            && qp.deltaq_v_ac == 0
+            && qp.deltaq_v_dc == 0;
+        if !fh.lossless_array[segment_id] {
+            fh.coded_lossless = false;
+        }
+        if qp.using_qmatrix {
+            let qm_level = if fh.lossless_array[segment_id] {
+                [15, 15, 15]
+            } else {
+                [qp.qm_y, qp.qm_u, qp.qm_v]
+            };
+            for (plane, level) in qm_level.iter().enumerate() {
+                fh.seg_qm_level[plane][segment_id] = *level;
+            }
+        }
     }
     fh.all_lossless =
         fh.coded_lossless && (fh.frame_size.frame_width == fh.frame_size.upscaled_width);
@@ -2080,85 +2705,166 @@ pub fn parse_frame_header<R: io::Read>(
     }
     fh.reduced_tx_set = br.f::<bool>(1)?; // f(1)
     fh.global_motion_params = parse_global_motion_params(&mut br, &fh)?; // global_motion_params()
-    fh.film_grain_params = parse_film_grain_params(&mut br, sh, &fh)?; // film_grain_params()
+    fh.film_grain_params = parse_film_grain_params(&mut br, sh, &fh, rfman)?; // film_grain_params()
 
-    Some(fh)
+    Ok((fh, br.position()))
 }
 
 ///
-/// parse tile_list_obu()
+/// parse tile_group_obu()
 ///
-pub fn parse_tile_list<R: io::Read>(bs: &mut R) -> Option<TileList> {
-    let mut br = BitReader::new(bs);
-    let mut tl = TileList::default();
+/// `tile_num` is TileNum of the next tile in the current frame (0 for the first tile group).
+///
+pub fn parse_tile_group(
+    data: &[u8],
+    fh: &FrameHeader,
+    tile_num: u16,
+) -> Result<TileGroup, ParseError> {
+    let mut br = BitReader::new(data);
+    let mut tg = TileGroup::default();
+    let ti = &fh.tile_info;
+
+    let num_tiles = ti.tile_cols * ti.tile_rows;
+    tg.tile_start_and_end_present_flag = false;
+    if num_tiles > 1 {
+        tg.tile_start_and_end_present_flag = br.f::<bool>(1)?; // f(1)
+    }
+    if num_tiles == 1 || !tg.tile_start_and_end_present_flag {
+        tg.tg_start = 0;
+        tg.tg_end = num_tiles - 1;
+    } else {
+        let tile_bits = ti.tile_cols_log2 + ti.tile_rows_log2;
+        tg.tg_start = br.f::<u16>(tile_bits)?; // f(tileBits)
+        tg.tg_end = br.f::<u16>(tile_bits)?; // f(tileBits)
+    }
+    // It is a requirement of bitstream conformance that the value of tg_start is equal to the
+    // value of TileNum at the point that tile_group_obu is invoked.
+    if tg.tg_start != tile_num {
+        return Err(br.error(ParseErrorKind::Conformance("tg_start")));
+    }
+    // It is a requirement of bitstream conformance that the value of tg_end is greater than or
+    // equal to tg_start, and less than NumTiles.
+    if tg.tg_end < tg.tg_start || num_tiles <= tg.tg_end {
+        return Err(br.error(ParseErrorKind::Conformance("tg_end")));
+    }
+    br.byte_alignment(); // byte_alignment()
+
+    for tile_num in tg.tg_start..=tg.tg_end {
+        let last_tile = tile_num == tg.tg_end;
+        let tile_size = if last_tile {
+            (br.remaining_bits() / 8) as u32
+        } else {
+            let tile_size_minus_1 = br.le(ti.tile_size_bytes)?; // le(TileSizeBytes)
+            match tile_size_minus_1.checked_add(1) {
+                Some(size) if size as u64 <= br.remaining_bits() / 8 => size,
+                _ => {
+                    return Err(br.error(ParseErrorKind::InvalidValue {
+                        element: "tile_size_minus_1",
+                        value: tile_size_minus_1 as i64,
+                    }))
+                }
+            }
+        };
+        tg.tiles.push(TileLocation {
+            tile_row: tile_num / ti.tile_cols,
+            tile_col: tile_num % ti.tile_cols,
+            offset: (br.position() / 8) as u32,
+            size: tile_size,
+        });
+        br.skip_bytes(tile_size)?; // tile data
+    }
 
-    tl.output_frame_width_in_tiles_minus_1 = br.f::<u8>(8)?;
-    tl.output_frame_height_in_tiles_minus_1 = br.f::<u8>(8)?;
-    tl.tile_count_minus_1 = br.f::<u16>(16)?;
+    Ok(tg)
+}
 
+///
+/// parse tile_list_obu()
+///
+pub fn parse_tile_list(data: &[u8]) -> Result<TileList, ParseError> {
+    let mut br = BitReader::new(data);
+    let mut tl = TileList {
+        output_frame_width_in_tiles_minus_1: br.f::<u8>(8)?, // f(8)
+        output_frame_height_in_tiles_minus_1: br.f::<u8>(8)?, // f(8)
+        tile_count_minus_1: br.f::<u16>(16)?,                // f(16)
+        ..Default::default()
+    };
     for _ in 0..=tl.tile_count_minus_1 {
-        tl.tile_list_entries.push(parse_tile_list_entry(&mut br)?);
+        tl.tile_list_entries.push(parse_tile_list_entry(&mut br)?); // tile_list_entry()
     }
 
-    Some(tl)
+    Ok(tl)
 }
 
 ///
 /// parse tile_list_entry()
 ///
-fn parse_tile_list_entry<R: io::Read>(br: &mut BitReader<R>) -> Option<TileListEntry> {
+fn parse_tile_list_entry(br: &mut BitReader) -> Result<TileListEntry, ParseError> {
     let mut tle = TileListEntry::default();
 
-    tle.anchor_frame_idx = br.f::<u8>(8)?;
-    tle.anchor_tile_row = br.f::<u8>(8)?;
-    tle.anchor_tile_col = br.f::<u8>(8)?;
-    tle.tile_data_size_minus_1 = br.f::<u16>(16)?;
+    tle.anchor_frame_idx = br.f::<u8>(8)?; // f(8)
+    tle.anchor_tile_row = br.f::<u8>(8)?; // f(8)
+    tle.anchor_tile_col = br.f::<u8>(8)?; // f(8)
+    tle.tile_data_size_minus_1 = br.f::<u16>(16)?; // f(16)
+    tle.offset = (br.position() / 8) as u32;
+    tle.size = tle.tile_data_size_minus_1 as u32 + 1;
+    br.skip_bytes(tle.size)?; // coded tile data f(N)
 
-    Some(tle)
+    Ok(tle)
 }
 
 ///
 /// parse metadata_obu()
 ///
-pub fn parse_metadata_obu<R: io::Read>(bs: &mut R) -> io::Result<MetadataObu> {
-    let (_metadata_type_len, metadata_type) = leb128(bs)?;
-    let mut br = BitReader::new(bs);
+pub fn parse_metadata_obu(data: &[u8]) -> Result<MetadataObu, ParseError> {
+    let mut br = BitReader::new(data);
+    let (_metadata_type_len, metadata_type) = br.leb128()?; // leb128()
 
-    let metadata = match metadata_type {
+    match metadata_type {
         METADATA_TYPE_HDR_CLL => parse_hdr_cll_metadata(&mut br),
         METADATA_TYPE_HDR_MDCV => parse_hdr_mdcv_metadata(&mut br),
         METADATA_TYPE_SCALABILITY => parse_scalability_metadata(&mut br),
         METADATA_TYPE_ITUT_T35 => parse_itu_t_t35_metadata(&mut br),
         METADATA_TYPE_TIMECODE => parse_timecode_metadata(&mut br),
-        _ => None,
-    };
+        METADATA_TYPE_UNREGISTERED_FIRST..=METADATA_TYPE_UNREGISTERED_LAST => {
+            Ok(MetadataObu::Unregistered {
+                metadata_type,
+                payload: parse_raw_metadata(&mut br),
+            })
+        }
+        _ => Ok(MetadataObu::Reserved {
+            metadata_type,
+            payload: parse_raw_metadata(&mut br),
+        }),
+    }
+}
 
-    if let Some(metadata_obu) = metadata {
-        Ok(metadata_obu)
-    } else {
-        Err(io::Error::new(
-            io::ErrorKind::InvalidData,
-            "Failed parsing metadata OBU or invalid metadata_type",
-        ))
+///
+/// read unregistered or reserved metadata payload
+///
+fn parse_raw_metadata(br: &mut BitReader) -> Vec<u8> {
+    let mut payload = Vec::new();
+    while let Ok(byte) = br.f::<u8>(8) {
+        payload.push(byte);
     }
+    strip_trailing_bits(&payload)
 }
 
 ///
 /// parse metadata_hdr_cll()
 ///
-fn parse_hdr_cll_metadata<R: io::Read>(br: &mut BitReader<R>) -> Option<MetadataObu> {
+fn parse_hdr_cll_metadata(br: &mut BitReader) -> Result<MetadataObu, ParseError> {
     let mut meta = HdrCllMetadata::default();
 
     meta.max_cll = br.f::<u16>(16)?; // f(16)
     meta.max_fall = br.f::<u16>(16)?; // f(16)
 
-    Some(MetadataObu::HdrCll(meta))
+    Ok(MetadataObu::HdrCll(meta))
 }
 
 ///
 /// parse metadata_hdr_mdcv()
 ///
-fn parse_hdr_mdcv_metadata<R: io::Read>(br: &mut BitReader<R>) -> Option<MetadataObu> {
+fn parse_hdr_mdcv_metadata(br: &mut BitReader) -> Result<MetadataObu, ParseError> {
     let mut meta = HdrMdcvMetadata::default();
 
     for i in 0..3 {
@@ -2171,27 +2877,27 @@ fn parse_hdr_mdcv_metadata<R: io::Read>(br: &mut BitReader<R>) -> Option<Metadat
     meta.luminance_max = br.f::<u32>(32)?; // f(32)
     meta.luminance_min = br.f::<u32>(32)?; // f(32)
 
-    Some(MetadataObu::HdrMdcv(meta))
+    Ok(MetadataObu::HdrMdcv(meta))
 }
 
 ///
 /// parse metadata_scalability()
 ///
-fn parse_scalability_metadata<R: io::Read>(br: &mut BitReader<R>) -> Option<MetadataObu> {
+fn parse_scalability_metadata(br: &mut BitReader) -> Result<MetadataObu, ParseError> {
     let mut meta = ScalabilityMetadata::default();
 
     meta.scalability_mode_idc = br.f::<u8>(8)?; // f(8)
     if meta.scalability_mode_idc == SCALABILITY_SS {
-        meta.scalability_structure = parse_scalability_structure(br);
+        meta.scalability_structure = Some(parse_scalability_structure(br)?);
     }
 
-    Some(MetadataObu::Scalability(meta))
+    Ok(MetadataObu::Scalability(meta))
 }
 
 ///
 /// parse scalability_structure()
 ///
-fn parse_scalability_structure<R: io::Read>(br: &mut BitReader<R>) -> Option<ScalabilityStructure> {
+fn parse_scalability_structure(br: &mut BitReader) -> Result<ScalabilityStructure, ParseError> {
     let mut ss = ScalabilityStructure::default();
 
     ss.spatial_layers_cnt_minus_1 = br.f::<u8>(2)?; // f(2)
@@ -2224,40 +2930,41 @@ fn parse_scalability_structure<R: io::Read>(br: &mut BitReader<R>) -> Option<Sca
                 .push(br.f::<bool>(1)?); // f(1)
             ss.temporal_group_ref_cnt.push(br.f::<u8>(3)?); // f(3)
 
+            ss.temporal_group_ref_pic_diff.push(Vec::new());
             for _ in 0..ss.temporal_group_ref_cnt[i] {
                 ss.temporal_group_ref_pic_diff[i].push(br.f::<u8>(8)?); // f(8)
             }
         }
     }
 
-    Some(ss)
+    Ok(ss)
 }
 
 ///
 /// parse metadata_itut_t35()
 ///
-fn parse_itu_t_t35_metadata<R: io::Read>(br: &mut BitReader<R>) -> Option<MetadataObu> {
+fn parse_itu_t_t35_metadata(br: &mut BitReader) -> Result<MetadataObu, ParseError> {
     let mut meta = ItutT35Metadata::default();
 
     meta.itu_t_t35_country_code = br.f::<u8>(8)?; // f(8)
 
     meta.itu_t_t35_country_code_extension_byte = if meta.itu_t_t35_country_code == 0xFF {
-        br.f::<u8>(8) // f(8)
+        Some(br.f::<u8>(8)?) // f(8)
     } else {
         None
     };
 
-    while let Some(byte) = br.f::<u8>(8) {
+    while let Ok(byte) = br.f::<u8>(8) {
         meta.itu_t_t35_payload_bytes.push(byte);
     }
 
-    Some(MetadataObu::ItutT35(meta))
+    Ok(MetadataObu::ItutT35(meta))
 }
 
 ///
 /// parse metadata_timecode()
 ///
-fn parse_timecode_metadata<R: io::Read>(br: &mut BitReader<R>) -> Option<MetadataObu> {
+fn parse_timecode_metadata(br: &mut BitReader) -> Result<MetadataObu, ParseError> {
     let mut meta = TimecodeMetadata::default();
 
     meta.counting_type = br.f::<u8>(5)?; // f(5)
@@ -2295,5 +3002,508 @@ fn parse_timecode_metadata<R: io::Read>(br: &mut BitReader<R>) -> Option<Metadat
         // f(time_offset_length)
     }
 
-    Some(MetadataObu::Timecode(meta))
+    Ok(MetadataObu::Timecode(meta))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bitio::pack_bits;
+
+    fn sequence_header(order_hint_bits: u8) -> SequenceHeader {
+        SequenceHeader {
+            enable_order_hint: true,
+            order_hint_bits,
+            max_frame_width: 1920,
+            max_frame_height: 1080,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn set_frame_refs_short_signaling() {
+        let sh = sequence_header(7);
+        let mut rfman = av1::RefFrameManager::new();
+        rfman.ref_order_hint = [0, 8, 2, 6, 3, 1, 7, 5];
+        let mut fh = FrameHeader {
+            order_hint: 4,
+            last_frame_idx: 4,
+            gold_frame_idx: 0,
+            ..Default::default()
+        };
+        set_frame_refs(&mut fh, &sh, &rfman);
+        // LAST, LAST2, LAST3, GOLDEN, BWDREF, ALTREF2, ALTREF
+        assert_eq!(fh.ref_frame_idx[..REFS_PER_FRAME], [4, 2, 5, 0, 7, 3, 1]);
+    }
+
+    #[test]
+    fn set_frame_refs_forward_only() {
+        let sh = sequence_header(7);
+        let mut rfman = av1::RefFrameManager::new();
+        rfman.ref_order_hint = [0; NUM_REF_FRAMES];
+        let mut fh = FrameHeader {
+            order_hint: 1,
+            last_frame_idx: 0,
+            gold_frame_idx: 1,
+            ..Default::default()
+        };
+        set_frame_refs(&mut fh, &sh, &rfman);
+        // no backward references, remaining references use find_latest_forward()
+        assert_eq!(fh.ref_frame_idx[..REFS_PER_FRAME], [0, 7, 6, 1, 5, 4, 3]);
+    }
+
+    #[test]
+    fn frame_size_with_refs() {
+        let sh = sequence_header(7);
+        let mut rfman = av1::RefFrameManager::new();
+        rfman.ref_upscaled_width[5] = 640;
+        rfman.ref_frame_width[5] = 640;
+        rfman.ref_frame_height[5] = 360;
+        rfman.ref_render_width[5] = 632;
+        rfman.ref_render_height[5] = 352;
+        let mut fh = FrameHeader::default();
+        fh.ref_frame_idx[1] = 5;
+
+        // found_ref=0, found_ref=1
+        let data = pack_bits(&[(0, 1), (1, 1)]);
+        let mut br = BitReader::new(&data);
+        let (fs, rs) = parse_frame_size_with_refs(&mut br, &sh, &fh, &rfman).unwrap();
+        assert_eq!(
+            (fs.upscaled_width, fs.frame_width, fs.frame_height),
+            (640, 640, 360)
+        );
+        assert_eq!((rs.render_width, rs.render_height), (632, 352));
+        assert_eq!(br.position(), 2);
+
+        // found_ref=0 for all references, frame_size() and render_size()
+        let data = pack_bits(&[(0, REFS_PER_FRAME), (0, 1)]);
+        let mut br = BitReader::new(&data);
+        let (fs, rs) = parse_frame_size_with_refs(&mut br, &sh, &fh, &rfman).unwrap();
+        assert_eq!(
+            (fs.upscaled_width, fs.frame_width, fs.frame_height),
+            (1920, 1920, 1080)
+        );
+        assert_eq!((rs.render_width, rs.render_height), (1920, 1080));
+    }
+
+    #[test]
+    fn film_grain_params_from_reference() {
+        let sh = SequenceHeader {
+            film_grain_params_present: true,
+            ..Default::default()
+        };
+        let mut rfman = av1::RefFrameManager::new();
+        let mut key_frame = FrameHeader {
+            frame_type: KEY_FRAME,
+            refresh_frame_flags: 1 << 5,
+            ..Default::default()
+        };
+        key_frame.film_grain_params = FilmGrainParams {
+            apply_grain: true,
+            grain_seed: 1,
+            update_grain: true,
+            num_y_points: 2,
+            ..Default::default()
+        };
+        rfman.update_process(&key_frame); // save_grain_params(5)
+
+        let mut fh = FrameHeader {
+            frame_type: INTER_FRAME,
+            show_frame: true,
+            ..Default::default()
+        };
+        fh.ref_frame_idx[3] = 5;
+        // apply_grain=1, grain_seed, update_grain=0, film_grain_params_ref_idx=5
+        let data = pack_bits(&[(1, 1), (0xbeef, 16), (0, 1), (5, 3)]);
+        let fgp = parse_film_grain_params(&mut BitReader::new(&data), &sh, &fh, &rfman).unwrap();
+        assert!(fgp.apply_grain);
+        assert!(!fgp.update_grain);
+        assert_eq!(fgp.grain_seed, 0xbeef);
+        assert_eq!(fgp.film_grain_params_ref_idx, 5);
+        assert_eq!(fgp.num_y_points, 2);
+
+        // film_grain_params_ref_idx is not in ref_frame_idx[]
+        let data = pack_bits(&[(1, 1), (0xbeef, 16), (0, 1), (6, 3)]);
+        assert_eq!(
+            parse_film_grain_params(&mut BitReader::new(&data), &sh, &fh, &rfman)
+                .unwrap_err()
+                .kind,
+            ParseErrorKind::Conformance("film_grain_params_ref_idx")
+        );
+    }
+
+    #[test]
+    fn temporal_point_info() {
+        let mut sh = sequence_header(7);
+        let data = pack_bits(&[(0x5a, 8)]);
+        let mut br = BitReader::new(&data);
+        assert_eq!(
+            parse_temporal_point_info(&mut br, &sh).unwrap_err().kind,
+            ParseErrorKind::Conformance("temporal_point_info() requires decoder_model_info()")
+        );
+        sh.decoder_model_info = Some(DecoderModelInfo {
+            frame_presentation_time_length: 8,
+            ..Default::default()
+        });
+        assert_eq!(parse_temporal_point_info(&mut br, &sh), Ok(0x5a));
+    }
+
+    #[test]
+    fn get_qindex_with_segmentation() {
+        let mut fh = FrameHeader::default();
+        fh.quantization_params.base_q_idx = 100;
+        fh.segmentation_params.segmentation_enabled = true;
+        fh.segmentation_params.feature_enabled[1][SEG_LVL_ALT_Q] = true;
+        fh.segmentation_params.feature_data[1][SEG_LVL_ALT_Q] = -120;
+        fh.segmentation_params.feature_enabled[2][SEG_LVL_ALT_Q] = true;
+        fh.segmentation_params.feature_data[2][SEG_LVL_ALT_Q] = 20;
+        assert_eq!(get_qindex(&fh, true, 0), 100);
+        assert_eq!(get_qindex(&fh, true, 1), 0); // clamped
+        assert_eq!(get_qindex(&fh, true, 2), 120);
+        fh.delta_q_params.delta_q_present = true;
+        assert_eq!(get_qindex(&fh, false, 0), 100);
+        assert_eq!(get_qindex(&fh, false, 2), 120);
+    }
+
+    #[test]
+    fn load_previous_segmentation_params() {
+        let mut rfman = av1::RefFrameManager::new();
+        rfman.saved_feature_enabled[3][2][SEG_LVL_ALT_Q] = true;
+        rfman.saved_feature_data[3][2][SEG_LVL_ALT_Q] = -7;
+        let mut fh = FrameHeader {
+            primary_ref_frame: 2,
+            ..Default::default()
+        };
+        fh.ref_frame_idx[2] = 3;
+        load_previous(&mut fh, &rfman);
+        assert!(fh.segmentation_params.feature_enabled[2][SEG_LVL_ALT_Q]);
+        assert_eq!(fh.segmentation_params.feature_data[2][SEG_LVL_ALT_Q], -7);
+
+        setup_past_independence(&mut fh);
+        assert!(!fh.segmentation_params.feature_enabled[2][SEG_LVL_ALT_Q]);
+        assert_eq!(fh.segmentation_params.feature_data[2][SEG_LVL_ALT_Q], 0);
+    }
+
+    #[test]
+    fn load_previous_loop_filter_params() {
+        let mut rfman = av1::RefFrameManager::new();
+        rfman.saved_loop_filter_ref_deltas[3] = [1, 2, 3, 4, 5, 6, 7, 8];
+        rfman.saved_loop_filter_mode_deltas[3] = [-1, 1];
+        rfman.saved_gm_params[3][LAST_FRAME] = [1, 2, 3, 4, 5, 6];
+        let mut fh = FrameHeader {
+            primary_ref_frame: 2,
+            ..Default::default()
+        };
+        fh.ref_frame_idx[2] = 3;
+        load_previous(&mut fh, &rfman);
+        assert_eq!(
+            fh.loop_filter_params.loop_filter_ref_deltas,
+            [1, 2, 3, 4, 5, 6, 7, 8]
+        );
+        assert_eq!(fh.loop_filter_params.loop_filter_mode_deltas, [-1, 1]);
+        assert_eq!(
+            fh.global_motion_params.prev_gm_params[LAST_FRAME],
+            [1, 2, 3, 4, 5, 6]
+        );
+
+        setup_past_independence(&mut fh);
+        assert_eq!(
+            fh.loop_filter_params.loop_filter_ref_deltas,
+            [1, 0, 0, 0, -1, 0, -1, -1]
+        );
+        assert_eq!(fh.loop_filter_params.loop_filter_mode_deltas, [0, 0]);
+        assert_eq!(
+            fh.global_motion_params.prev_gm_params[LAST_FRAME],
+            [
+                0,
+                0,
+                1 << WARPEDMODEL_PREC_BITS,
+                0,
+                0,
+                1 << WARPEDMODEL_PREC_BITS
+            ]
+        );
+    }
+
+    fn two_tiles_frame_header(tile_size_bytes: usize) -> FrameHeader {
+        FrameHeader {
+            tile_info: TileInfo {
+                tile_cols: 2,
+                tile_rows: 1,
+                tile_cols_log2: 1,
+                tile_rows_log2: 0,
+                tile_size_bytes,
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn tile_group() {
+        let fh = two_tiles_frame_header(2);
+        // tile_start_and_end_present_flag=0, tile_size_minus_1=2, 3 bytes, 4 bytes
+        let data = [0x00, 0x02, 0x00, 1, 2, 3, 4, 5, 6, 7];
+        let tg = parse_tile_group(&data, &fh, 0).unwrap();
+        assert_eq!((tg.tg_start, tg.tg_end), (0, 1));
+        let tiles: Vec<_> = tg
+            .tiles
+            .iter()
+            .map(|t| (t.tile_row, t.tile_col, t.offset, t.size))
+            .collect();
+        assert_eq!(tiles, [(0, 0, 3, 3), (0, 1, 6, 4)]);
+    }
+
+    #[test]
+    fn tile_group_start_and_end() {
+        let mut fh = two_tiles_frame_header(2);
+        fh.tile_info.tile_rows = 2;
+        fh.tile_info.tile_rows_log2 = 1;
+        // tile_start_and_end_present_flag=1, tg_start=1, tg_end=2, tile_size_minus_1=0
+        let data = [0xb0, 0x00, 0x00, 1, 2, 3];
+        let tg = parse_tile_group(&data, &fh, 1).unwrap();
+        assert_eq!((tg.tg_start, tg.tg_end), (1, 2));
+        let tiles: Vec<_> = tg
+            .tiles
+            .iter()
+            .map(|t| (t.tile_row, t.tile_col, t.offset, t.size))
+            .collect();
+        assert_eq!(tiles, [(0, 1, 3, 1), (1, 0, 4, 2)]);
+
+        // tg_start is not equal to TileNum
+        assert_eq!(
+            parse_tile_group(&data, &fh, 0).unwrap_err(),
+            ParseError::new(ParseErrorKind::Conformance("tg_start"), 5)
+        );
+        // tg_start=2, tg_end=1
+        assert_eq!(
+            parse_tile_group(&[0xc8, 1, 2, 3], &fh, 2).unwrap_err().kind,
+            ParseErrorKind::Conformance("tg_end")
+        );
+        // tg_end=3 for 3 tiles
+        fh.tile_info.tile_cols = 3;
+        fh.tile_info.tile_cols_log2 = 2;
+        fh.tile_info.tile_rows = 1;
+        fh.tile_info.tile_rows_log2 = 0;
+        assert_eq!(
+            parse_tile_group(&[0x98, 1, 2, 3], &fh, 0).unwrap_err().kind,
+            ParseErrorKind::Conformance("tg_end")
+        );
+    }
+
+    #[test]
+    fn tile_group_invalid_tile_size() {
+        let fh = two_tiles_frame_header(2);
+        let data = [0x00, 0x10, 0x00, 1, 2, 3];
+        assert_eq!(
+            parse_tile_group(&data, &fh, 0).unwrap_err(),
+            ParseError::new(
+                ParseErrorKind::InvalidValue {
+                    element: "tile_size_minus_1",
+                    value: 0x10,
+                },
+                24
+            )
+        );
+        // tile_size_minus_1 + 1 overflows u32
+        let fh = two_tiles_frame_header(4);
+        let data = [0x00, 0xff, 0xff, 0xff, 0xff, 1, 2, 3];
+        assert_eq!(
+            parse_tile_group(&data, &fh, 0).unwrap_err().kind,
+            ParseErrorKind::InvalidValue {
+                element: "tile_size_minus_1",
+                value: 0xffff_ffff,
+            }
+        );
+    }
+
+    #[test]
+    fn obu_iter() {
+        let data = [
+            0x12, 0x00, // OBU_TEMPORAL_DELIMITER, obu_size=0
+            0x7e, 0x48, 0x01, 0xdd, // OBU_PADDING, temporal_id=2, spatial_id=1, obu_size=1
+            0x78, 0xaa, 0xbb, // OBU_PADDING without obu_size
+        ];
+        let obus: Vec<_> = ObuIter::new(&data).map(|obu| obu.unwrap()).collect();
+        assert_eq!(obus.len(), 3);
+        assert_eq!(obus[0].obu.obu_type, OBU_TEMPORAL_DELIMITER);
+        assert!(obus[0].payload.is_empty());
+        assert_eq!(obus[1].obu.obu_type, OBU_PADDING);
+        assert_eq!((obus[1].obu.temporal_id, obus[1].obu.spatial_id), (2, 1));
+        assert_eq!(obus[1].obu.header_len, 3);
+        assert_eq!(obus[1].payload, [0xdd]);
+        assert!(!obus[2].obu.obu_has_size_field);
+        assert_eq!(obus[2].payload, [0xaa, 0xbb]);
+
+        // parse_obu_header() on io::Read consumes OBU header only
+        let mut reader = &data[2..];
+        let obu = parse_obu_header(&mut reader, 4).unwrap();
+        assert_eq!((obu.header_len, obu.obu_size), (3, 1));
+        assert_eq!(reader, [0xdd, 0x78, 0xaa, 0xbb]);
+    }
+
+    #[test]
+    fn obu_iter_errors() {
+        let data = [0x12, 0x00, 0x7a, 0x05, 0xaa];
+        let mut iter = ObuIter::new(&data);
+        assert!(iter.next().unwrap().is_ok());
+        assert_eq!(
+            iter.next().unwrap().unwrap_err(),
+            ParseError::new(ParseErrorKind::Truncated, 32)
+        );
+        assert!(iter.next().is_none());
+
+        let data = [0x92, 0x00];
+        assert_eq!(
+            ObuIter::new(&data).next().unwrap().unwrap_err().kind,
+            ParseErrorKind::Conformance("obu_forbidden_bit")
+        );
+        let err = parse_obu_header(&mut &data[..], 2).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn tile_list() {
+        let data = [
+            0x00, 0x00, 0x00, 0x01, // 1x1 tiles, 2 tile_list_entry()
+            0x01, 0x00, 0x01, 0x00, 0x02, 0xa1, 0xa2, 0xa3, // anchor #1 tile(0,1), 3 bytes
+            0x02, 0x01, 0x00, 0x00, 0x00, 0xb1, // anchor #2 tile(1,0), 1 byte
+        ];
+        let tl = parse_tile_list(&data).unwrap();
+        assert_eq!(tl.tile_count_minus_1, 1);
+        let entries = &tl.tile_list_entries;
+        assert_eq!(entries.len(), 2);
+        assert_eq!(
+            (
+                entries[0].anchor_frame_idx,
+                entries[0].anchor_tile_row,
+                entries[0].anchor_tile_col
+            ),
+            (1, 0, 1)
+        );
+        assert_eq!((entries[0].offset, entries[0].size), (9, 3));
+        assert_eq!(
+            (
+                entries[1].anchor_frame_idx,
+                entries[1].anchor_tile_row,
+                entries[1].anchor_tile_col
+            ),
+            (2, 1, 0)
+        );
+        assert_eq!((entries[1].offset, entries[1].size), (17, 1));
+        let tile_data = |tle: &TileListEntry| &data[tle.offset as usize..][..tle.size as usize];
+        assert_eq!(tile_data(&entries[0]), [0xa1, 0xa2, 0xa3]);
+        assert_eq!(tile_data(&entries[1]), [0xb1]);
+
+        // coded tile data beyond the end of OBU
+        let err = parse_tile_list(&data[..17]).unwrap_err();
+        assert_eq!(err.kind, ParseErrorKind::Truncated);
+    }
+
+    #[test]
+    fn scalability_metadata() {
+        for mode in SCALABILITY_MODES.iter() {
+            let meta = ScalabilityMetadata {
+                scalability_mode_idc: mode.scalability_mode_idc,
+                scalability_structure: None,
+            };
+            assert_eq!(meta.mode().map(|m| m.name), Some(mode.name));
+        }
+
+        // metadata_type=METADATA_TYPE_SCALABILITY, scalability_mode_idc=4(L2T3)
+        let sc = match parse_metadata_obu(&[0x03, 0x04, 0x80]) {
+            Ok(MetadataObu::Scalability(sc)) => sc,
+            other => panic!("unexpected metadata {:?}", other),
+        };
+        let mode = sc.mode().unwrap();
+        assert_eq!(mode.name, "L2T3");
+        assert_eq!((mode.spatial_layers, mode.temporal_layers), (2, 3));
+        assert_eq!(mode.resolution_ratio, (2, 1));
+        assert_eq!(mode.inter_layer_prediction, InterLayerPrediction::Always);
+        assert_eq!(sc.layers(), Some((2, 3)));
+        assert!(sc.scalability_structure.is_none());
+
+        // SCALABILITY_SS: 3 spatial layers, temporal group of temporal_id 0 and 1
+        let sc = match parse_metadata_obu(&[0x03, 0x0e, 0x88, 0x02, 0x00, 0x30, 0x80]) {
+            Ok(MetadataObu::Scalability(sc)) => sc,
+            other => panic!("unexpected metadata {:?}", other),
+        };
+        assert!(sc.mode().is_none());
+        assert_eq!(
+            sc.scalability_structure
+                .as_ref()
+                .unwrap()
+                .temporal_group_temporal_id,
+            [0, 1]
+        );
+        assert_eq!(sc.layers(), Some((3, 2)));
+
+        // reserved scalability_mode_idc
+        let sc = ScalabilityMetadata {
+            scalability_mode_idc: 29,
+            scalability_structure: None,
+        };
+        assert!(sc.mode().is_none());
+        assert_eq!(sc.layers(), None);
+    }
+
+    #[test]
+    fn raw_metadata() {
+        match parse_metadata_obu(&[0x06, 0xab, 0xcd, 0x80, 0x00]) {
+            Ok(MetadataObu::Unregistered {
+                metadata_type,
+                payload,
+            }) => {
+                assert_eq!(metadata_type, 6);
+                assert_eq!(payload, [0xab, 0xcd]);
+            }
+            other => panic!("unexpected metadata {:?}", other),
+        }
+        // trailing_one_bit shares the last payload byte
+        match parse_metadata_obu(&[0x1f, 0x12, 0x3c]) {
+            Ok(MetadataObu::Unregistered {
+                metadata_type: 31,
+                payload,
+            }) => assert_eq!(payload, [0x12, 0x38]),
+            other => panic!("unexpected metadata {:?}", other),
+        }
+        // trailing_bits() only
+        match parse_metadata_obu(&[0x07, 0x80]) {
+            Ok(MetadataObu::Unregistered {
+                metadata_type: 7,
+                payload,
+            }) => assert!(payload.is_empty()),
+            other => panic!("unexpected metadata {:?}", other),
+        }
+
+        for &(data, reserved_type) in &[
+            (&[0x00, 0x55, 0x80][..], 0),
+            (&[0xc8, 0x01, 0x55, 0x80][..], 200),
+        ] {
+            match parse_metadata_obu(data) {
+                Ok(MetadataObu::Reserved {
+                    metadata_type,
+                    payload,
+                }) => {
+                    assert_eq!(metadata_type, reserved_type);
+                    assert_eq!(payload, [0x55]);
+                }
+                other => panic!("unexpected metadata {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn leading_bits() {
+        assert_eq!(
+            super::leading_bits(&[0xff, 0xff, 0xff], 12),
+            Some(vec![0xff, 0xf0])
+        );
+        assert_eq!(
+            super::leading_bits(&[0xff, 0xff], 16),
+            Some(vec![0xff, 0xff])
+        );
+        assert_eq!(super::leading_bits(&[0xff, 0xff], 17), None);
+        assert_eq!(strip_trailing_bits(&[0xab, 0xc8, 0x00]), [0xab, 0xc0]);
+    }
 }
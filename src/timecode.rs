@@ -0,0 +1,413 @@
+//
+// SMPTE timecode in metadata_timecode()
+//
+use crate::obu;
+use std::cmp;
+use std::fmt;
+
+// counting_type
+pub const COUNTING_NO_DROP_NO_OFFSET: u8 = 0; // no dropping of n_frames count values and no use of time_offset_value
+pub const COUNTING_NO_DROP: u8 = 1; // no dropping of n_frames count values
+pub const COUNTING_DROP_ZERO: u8 = 2; // dropping of individual zero values of n_frames count
+pub const COUNTING_DROP_MAX: u8 = 3; // dropping of individual n_frames count values equal to maxFps - 1
+pub const COUNTING_DROP_FRAME: u8 = 4; // dropping of n_frames 0 and 1 when seconds_value is 0 and minutes_value is not a multiple of 10
+pub const COUNTING_DROP_UNSPECIFIED: u8 = 5; // dropping of unspecified individual n_frames count values
+pub const COUNTING_DROP_UNSPECIFIED_MULTI: u8 = 6; // dropping of unspecified numbers of unspecified n_frames count values
+
+///
+/// Timecode value (HH:MM:SS:FF)
+///
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Timecode {
+    pub hours: u8,
+    pub minutes: u8,
+    pub seconds: u8,
+    pub frames: u16,
+    pub drop_frame: bool,
+}
+
+impl fmt::Display for Timecode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:02}:{:02}:{:02}{}{:02}",
+            self.hours,
+            self.minutes,
+            self.seconds,
+            if self.drop_frame { ';' } else { ':' },
+            self.frames
+        )
+    }
+}
+
+impl Timecode {
+    /// return true if the frame count is skipped in drop-frame counting
+    fn is_dropped(&self) -> bool {
+        self.drop_frame && self.seconds == 0 && !self.minutes.is_multiple_of(10) && self.frames < 2
+    }
+
+    /// number of frames since 00:00:00:00
+    fn frame_count(&self, fps: i64, drop_frame: bool) -> i64 {
+        let minutes = self.hours as i64 * 60 + self.minutes as i64;
+        let mut count = (minutes * 60 + self.seconds as i64) * fps + self.frames as i64;
+        if drop_frame {
+            // n_frames 0 and 1 are skipped in each minute except multiples of 10
+            count -= 2 * (minutes - minutes / 10);
+            if self.is_dropped() {
+                // skipped count value is counted as the preceding frame
+                count += 1 - self.frames as i64;
+            }
+        }
+        count
+    }
+
+    /// timecode of the frame count since 00:00:00:00
+    fn from_frame_count(mut count: i64, fps: i64, drop_frame: bool) -> Timecode {
+        if drop_frame {
+            let frames_per_10min = 600 * fps - 18;
+            let frames_per_min = 60 * fps - 2;
+            let (d, r) = (count / frames_per_10min, count % frames_per_10min);
+            count += 18 * d;
+            if r > 1 {
+                count += 2 * ((r - 2) / frames_per_min);
+            }
+        }
+        let seconds = count / fps;
+        Timecode {
+            hours: (seconds / 3600 % 24) as u8,
+            minutes: (seconds / 60 % 60) as u8,
+            seconds: (seconds % 60) as u8,
+            frames: cmp::min(count % fps, u16::MAX as i64) as u16,
+            drop_frame,
+        }
+    }
+
+    /// advance timecode by n frames (wrap around at 24 hours)
+    pub fn advance(&self, n: i64, fps: u32) -> Timecode {
+        let fps = fps.max(1) as i64;
+        // drop-frame counting skips 2 values per minute
+        let drop_frame = self.drop_frame && fps > 2;
+        let frames_per_day = if drop_frame {
+            24 * 60 * 60 * fps - 24 * 6 * 18
+        } else {
+            24 * 60 * 60 * fps
+        };
+        let count = (self.frame_count(fps, drop_frame) + n).rem_euclid(frames_per_day);
+        Timecode {
+            drop_frame: self.drop_frame,
+            ..Timecode::from_frame_count(count, fps, drop_frame)
+        }
+    }
+}
+
+///
+/// Timecode continuity error
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TimecodeError {
+    // timecode jumps without discontinuity_flag
+    Gap {
+        expected: Timecode,
+        actual: Timecode,
+    },
+    // same timecode on different frames
+    Repeat(Timecode),
+    // dropped frame count is used, or cnt_dropped_flag mismatch
+    DropFrame(Timecode),
+}
+
+impl fmt::Display for TimecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TimecodeError::Gap { expected, actual } => {
+                write!(f, "timecode gap: expected {}, actual {}", expected, actual)
+            }
+            TimecodeError::Repeat(tc) => write!(f, "timecode repeat: {}", tc),
+            TimecodeError::DropFrame(tc) => write!(f, "timecode drop-frame error: {}", tc),
+        }
+    }
+}
+
+///
+/// Timecode state tracker
+///
+#[derive(Debug, Default)]
+pub struct TimecodeTracker {
+    pub last: Option<(i64, Timecode)>, // (present order, timecode) of the last metadata_timecode()
+}
+
+impl TimecodeTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// update timecode state with metadata_timecode() for the frame of `present_order`, return the timecode and continuity errors
+    pub fn update(
+        &mut self,
+        present_order: i64,
+        meta: &obu::TimecodeMetadata,
+        fps: u32,
+    ) -> (Timecode, Vec<TimecodeError>) {
+        let mut errors = Vec::new();
+        let prev = self.last.map(|(_, tc)| tc).unwrap_or_default();
+        let mut tc = Timecode {
+            hours: prev.hours,
+            minutes: prev.minutes,
+            seconds: prev.seconds,
+            frames: meta.n_frames,
+            drop_frame: meta.counting_type == COUNTING_DROP_FRAME,
+        };
+        if meta.full_timestamp_flag || meta.seconds_flag {
+            tc.seconds = meta.seconds_value;
+        }
+        if meta.full_timestamp_flag || meta.minutes_flag {
+            tc.minutes = meta.minutes_value;
+        }
+        if meta.full_timestamp_flag || meta.hours_flag {
+            tc.hours = meta.hours_value;
+        }
+
+        if tc.is_dropped() {
+            errors.push(TimecodeError::DropFrame(tc));
+        }
+        if let Some((last_order, last_tc)) = self.last {
+            let n = present_order - last_order;
+            if n > 0 && !meta.discontinuity_flag {
+                let expected = last_tc.advance(n, fps);
+                // timecode after skipping an individual n_frames value (indicated by cnt_dropped_flag)
+                let skipped_expected = match meta.counting_type {
+                    COUNTING_DROP_ZERO if expected.frames == 0 => Some(expected.advance(1, fps)),
+                    COUNTING_DROP_MAX if expected.frames as u32 + 1 == fps => {
+                        Some(expected.advance(1, fps))
+                    }
+                    _ => None,
+                };
+                // n_frames may jump by any number of dropped values
+                let unspecified = matches!(
+                    meta.counting_type,
+                    COUNTING_DROP_UNSPECIFIED | COUNTING_DROP_UNSPECIFIED_MULTI
+                );
+                if tc == last_tc {
+                    errors.push(TimecodeError::Repeat(tc));
+                } else if unspecified {
+                    // cnt_dropped_flag may indicate any skipping
+                } else if skipped_expected == Some(tc) {
+                    // skipping is optional, but shall be indicated by cnt_dropped_flag
+                    if n == 1 && !meta.cnt_dropped_flag {
+                        errors.push(TimecodeError::DropFrame(tc));
+                    }
+                } else if tc != expected {
+                    errors.push(TimecodeError::Gap {
+                        expected: match skipped_expected {
+                            Some(skipped) if meta.cnt_dropped_flag => skipped,
+                            _ => expected,
+                        },
+                        actual: tc,
+                    });
+                } else if n == 1 {
+                    // cnt_dropped_flag indicates the skipping of n_frames values
+                    let skipped = match meta.counting_type {
+                        COUNTING_DROP_FRAME => {
+                            tc.seconds == 0 && !tc.minutes.is_multiple_of(10) && tc.frames == 2
+                        }
+                        COUNTING_DROP_ZERO | COUNTING_DROP_MAX => meta.cnt_dropped_flag,
+                        _ => false,
+                    };
+                    if skipped != meta.cnt_dropped_flag {
+                        errors.push(TimecodeError::DropFrame(tc));
+                    }
+                }
+            }
+        }
+        self.last = Some((present_order, tc));
+        (tc, errors)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn timecode(hours: u8, minutes: u8, seconds: u8, frames: u16, drop_frame: bool) -> Timecode {
+        Timecode {
+            hours,
+            minutes,
+            seconds,
+            frames,
+            drop_frame,
+        }
+    }
+
+    // metadata_timecode() with full_timestamp_flag=1
+    fn full_timestamp(counting_type: u8, tc: Timecode) -> obu::TimecodeMetadata {
+        obu::TimecodeMetadata {
+            counting_type,
+            full_timestamp_flag: true,
+            n_frames: tc.frames,
+            seconds_value: tc.seconds,
+            minutes_value: tc.minutes,
+            hours_value: tc.hours,
+            ..Default::default()
+        }
+    }
+
+    // update tracker with (present_order, metadata_timecode()), return errors of the last one
+    fn track(metas: &[(i64, &obu::TimecodeMetadata)], fps: u32) -> Vec<TimecodeError> {
+        let mut tracker = TimecodeTracker::new();
+        let mut errors = Vec::new();
+        for &(order, meta) in metas {
+            errors = tracker.update(order, meta, fps).1;
+        }
+        errors
+    }
+
+    #[test]
+    fn advance() {
+        let tc = timecode(23, 59, 59, 29, false);
+        assert_eq!(tc.advance(1, 30), timecode(0, 0, 0, 0, false));
+        assert_eq!(tc.advance(31, 30), timecode(0, 0, 1, 0, false));
+        // tick rate as fps does not overflow frames
+        let tc = timecode(0, 0, 0, 0, false).advance(1_000_000_007, 90000);
+        assert_eq!(tc, timecode(3, 5, 11, 10007, false));
+    }
+
+    #[test]
+    fn advance_drop_frame() {
+        let tc = timecode(0, 0, 59, 29, true);
+        assert_eq!(tc.advance(1, 30), timecode(0, 1, 0, 2, true));
+        assert_eq!(
+            timecode(0, 9, 59, 29, true).advance(1, 30),
+            timecode(0, 10, 0, 0, true)
+        );
+        // 17982 frames in 10 minutes
+        assert_eq!(
+            timecode(0, 0, 0, 0, true).advance(17982, 30),
+            timecode(0, 10, 0, 0, true)
+        );
+        // skipped value is advanced from the preceding frame
+        assert_eq!(
+            timecode(0, 1, 0, 0, true).advance(1, 30),
+            timecode(0, 1, 0, 2, true)
+        );
+
+        let mut tc = timecode(0, 58, 12, 3, true);
+        for _ in 0..5000 {
+            tc = tc.advance(1, 30);
+            assert!(!tc.is_dropped());
+        }
+        assert_eq!(tc, timecode(0, 58, 12, 3, true).advance(5000, 30));
+    }
+
+    #[test]
+    fn partial_update() {
+        let first = full_timestamp(COUNTING_NO_DROP, timecode(1, 2, 3, 4, false));
+        let mut tracker = TimecodeTracker::new();
+        let (tc, errors) = tracker.update(0, &first, 30);
+        assert_eq!(tc, timecode(1, 2, 3, 4, false));
+        assert!(errors.is_empty());
+
+        // full_timestamp_flag=0 keeps hours, minutes and seconds
+        let meta = obu::TimecodeMetadata {
+            counting_type: COUNTING_NO_DROP,
+            n_frames: 5,
+            ..Default::default()
+        };
+        let (tc, errors) = tracker.update(1, &meta, 30);
+        assert_eq!(tc, timecode(1, 2, 3, 5, false));
+        assert!(errors.is_empty());
+
+        // seconds_flag=1
+        let meta = obu::TimecodeMetadata {
+            counting_type: COUNTING_NO_DROP,
+            n_frames: 0,
+            seconds_flag: true,
+            seconds_value: 4,
+            ..Default::default()
+        };
+        let (tc, errors) = tracker.update(26, &meta, 30);
+        assert_eq!(tc, timecode(1, 2, 4, 0, false));
+        assert!(errors.is_empty());
+    }
+
+    #[test]
+    fn drop_frame_minute_boundary() {
+        let last = full_timestamp(COUNTING_DROP_FRAME, timecode(0, 0, 59, 29, true));
+        let mut next = full_timestamp(COUNTING_DROP_FRAME, timecode(0, 1, 0, 2, true));
+        next.cnt_dropped_flag = true;
+        assert!(track(&[(0, &last), (1, &next)], 30).is_empty());
+
+        // cnt_dropped_flag=0 on skipping
+        next.cnt_dropped_flag = false;
+        assert_eq!(
+            track(&[(0, &last), (1, &next)], 30),
+            [TimecodeError::DropFrame(timecode(0, 1, 0, 2, true))]
+        );
+
+        // skipped n_frames value is used
+        let dropped = full_timestamp(COUNTING_DROP_FRAME, timecode(0, 1, 0, 0, true));
+        assert_eq!(
+            track(&[(0, &last), (1, &dropped)], 30),
+            [
+                TimecodeError::DropFrame(timecode(0, 1, 0, 0, true)),
+                TimecodeError::Gap {
+                    expected: timecode(0, 1, 0, 2, true),
+                    actual: timecode(0, 1, 0, 0, true),
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn discontinuity_gap_and_repeat() {
+        let first = full_timestamp(COUNTING_NO_DROP, timecode(0, 0, 10, 0, false));
+        let mut jump = full_timestamp(COUNTING_NO_DROP, timecode(0, 5, 0, 0, false));
+        assert_eq!(
+            track(&[(0, &first), (1, &jump)], 30),
+            [TimecodeError::Gap {
+                expected: timecode(0, 0, 10, 1, false),
+                actual: timecode(0, 5, 0, 0, false),
+            }]
+        );
+        jump.discontinuity_flag = true;
+        assert!(track(&[(0, &first), (1, &jump)], 30).is_empty());
+
+        assert_eq!(
+            track(&[(0, &first), (1, &first)], 30),
+            [TimecodeError::Repeat(timecode(0, 0, 10, 0, false))]
+        );
+    }
+
+    #[test]
+    fn drop_zero() {
+        let last = full_timestamp(COUNTING_DROP_ZERO, timecode(0, 0, 0, 29, false));
+        // dropping is optional
+        let zero = full_timestamp(COUNTING_DROP_ZERO, timecode(0, 0, 1, 0, false));
+        assert!(track(&[(0, &last), (1, &zero)], 30).is_empty());
+
+        let mut skipped = full_timestamp(COUNTING_DROP_ZERO, timecode(0, 0, 1, 1, false));
+        skipped.cnt_dropped_flag = true;
+        assert!(track(&[(0, &last), (1, &skipped)], 30).is_empty());
+        skipped.cnt_dropped_flag = false;
+        assert_eq!(
+            track(&[(0, &last), (1, &skipped)], 30),
+            [TimecodeError::DropFrame(timecode(0, 0, 1, 1, false))]
+        );
+    }
+
+    #[test]
+    fn drop_max() {
+        let last = full_timestamp(COUNTING_DROP_MAX, timecode(0, 0, 0, 28, false));
+        // dropping is optional
+        let max = full_timestamp(COUNTING_DROP_MAX, timecode(0, 0, 0, 29, false));
+        assert!(track(&[(0, &last), (1, &max)], 30).is_empty());
+
+        let mut skipped = full_timestamp(COUNTING_DROP_MAX, timecode(0, 0, 1, 0, false));
+        skipped.cnt_dropped_flag = true;
+        assert!(track(&[(0, &last), (1, &skipped)], 30).is_empty());
+        skipped.cnt_dropped_flag = false;
+        assert_eq!(
+            track(&[(0, &last), (1, &skipped)], 30),
+            [TimecodeError::DropFrame(timecode(0, 0, 1, 0, false))]
+        );
+    }
+}